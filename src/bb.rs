@@ -0,0 +1,329 @@
+use crate::interp::Stop;
+use crate::parser::{Dir, Instr, Program, RunCmd};
+use std::error::Error;
+use std::fmt;
+
+/// states are named by a single letter, `A` is the start state
+pub const STATES: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+/// symbols are single digits, `0` is the blank
+pub const SYMBOLS: &str = "0123456789";
+
+pub const START: &str = "A";
+pub const BLANK: &str = "0";
+
+fn state(i: usize) -> &'static str {
+    &STATES[i..i + 1]
+}
+
+fn symbol(i: usize) -> &'static str {
+    &SYMBOLS[i..i + 1]
+}
+
+#[derive(Debug)]
+pub enum BbErr {
+    /// the groups of the states do not all have the same, non-zero multiple of 3 length
+    Shape,
+    TooManyStates,
+    /// a transition of `state` on `symbol` that is not `<WRITE><L|R><NEXT>` or `---`
+    Transition { state: usize, symbol: usize },
+}
+
+impl Error for BbErr {}
+
+impl fmt::Display for BbErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BbErr::Shape => write!(
+                f,
+                "expected `_` separated states with 3 characters per symbol, like `1RB1LB_1LA1RZ`"
+            ),
+            BbErr::TooManyStates => write!(f, "at most {} states are supported", STATES.len()),
+            BbErr::Transition { state: s, symbol: sym } => write!(
+                f,
+                "transition of state {} on {} is not `<WRITE><L|R><NEXT>` or `---`",
+                state(*s),
+                symbol(*sym)
+            ),
+        }
+    }
+}
+
+/// A machine in the standard busy beaver notation, like `1RB1LB_1LA1RZ`.
+/// Each `_` separated group is a state starting from `A`, with a `<WRITE><L|R><NEXT>` transition
+/// per symbol starting from `0`. Transitions to a letter that is not a state halt in it,
+/// `---` leaves a transition undefined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub states: usize,
+    pub symbols: usize,
+    /// `(write, dir, next)` indexed by `state * symbols + read`, `next` is a halt state when it is not below `states`
+    pub transitions: Vec<Option<(u8, Dir, u8)>>,
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, t) in self.transitions.iter().enumerate() {
+            if i > 0 && i % self.symbols == 0 {
+                write!(f, "_")?;
+            }
+            match *t {
+                Some((write, dir, next)) => {
+                    let dir = match dir {
+                        Dir::Left => 'L',
+                        _ => 'R',
+                    };
+                    write!(f, "{write}{dir}{}", state(next as usize))?
+                }
+                None => write!(f, "---")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// how a machine ended up after [`Table::run`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub steps: usize,
+    /// non-blank cells on the tape
+    pub ones: usize,
+    /// the state the machine stopped in
+    pub state: &'static str,
+    pub stop: Stop<'static>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} steps, {} ones, ", self.steps, self.ones)?;
+        match self.stop {
+            Stop::Halted => write!(f, "halted with {}", self.state),
+            stop => write!(f, "{stop}"),
+        }
+    }
+}
+
+impl Table {
+    pub fn parse(notation: &str) -> Result<Table, BbErr> {
+        let groups: Vec<&[u8]> = notation.trim().split('_').map(str::as_bytes).collect();
+        let width = groups[0].len();
+        if width == 0 || !width.is_multiple_of(3) || groups.iter().any(|g| g.len() != width) {
+            return Err(BbErr::Shape);
+        }
+        if groups.len() > STATES.len() {
+            return Err(BbErr::TooManyStates);
+        }
+        let symbols = width / 3;
+        if symbols > SYMBOLS.len() {
+            return Err(BbErr::Shape);
+        }
+
+        let mut transitions = Vec::new();
+        for (s, group) in groups.iter().enumerate() {
+            for (sym, t) in group.chunks(3).enumerate() {
+                let err = BbErr::Transition { state: s, symbol: sym };
+                if t.iter().all(|&c| c == b'-' || c == b'?') {
+                    transitions.push(None);
+                    continue;
+                }
+                let write = match t[0] {
+                    c @ b'0'..=b'9' if ((c - b'0') as usize) < symbols => c - b'0',
+                    _ => return Err(err),
+                };
+                let dir = match t[1] {
+                    b'L' => Dir::Left,
+                    b'R' => Dir::Right,
+                    _ => return Err(err),
+                };
+                let next = match t[2] {
+                    c @ b'A'..=b'Z' => c - b'A',
+                    _ => return Err(err),
+                };
+                transitions.push(Some((write, dir, next)));
+            }
+        }
+        Ok(Table {
+            states: groups.len(),
+            symbols,
+            transitions,
+        })
+    }
+
+    /// The program of the machine with a run from `A` on a blank tape.
+    pub fn program(&self) -> Program<'static> {
+        let mut program = Vec::new();
+        let mut halt_syms = Vec::new();
+        for (i, t) in self.transitions.iter().enumerate() {
+            let Some((write, dir, next)) = *t else {
+                continue;
+            };
+            let next_state = state(next as usize);
+            if next as usize >= self.states && !halt_syms.contains(&next_state) {
+                halt_syms.push(next_state);
+            }
+            program.push(Instr {
+                state: state(i / self.symbols),
+                read: symbol(i % self.symbols),
+                write: symbol(write as usize),
+                dir,
+                next_state,
+            });
+        }
+
+        let runs = vec![RunCmd {
+            tape: vec![BLANK],
+            head: 0,
+            state: START,
+            expect: None,
+        }];
+        Program::from_table(runs, halt_syms, None, program)
+    }
+
+    /// Runs the machine from `A` on a blank tape. The tape is a byte per cell
+    /// and transitions are looked up by index, which is much faster than [`crate::interp::Machine`].
+    pub fn run(&self, max_steps: Option<usize>) -> Report {
+        let mut tape = vec![0u8; 64];
+        let mut head = tape.len() / 2;
+        let (mut state, mut steps) = (0, 0);
+
+        let stop = loop {
+            if state >= self.states {
+                break Stop::Halted;
+            }
+            if max_steps.is_some_and(|max| steps >= max) {
+                break Stop::StepLimit;
+            }
+            let read = tape[head];
+            let Some((write, dir, next)) = self.transitions[state * self.symbols + read as usize] else {
+                break Stop::Missing {
+                    state: self::state(state),
+                    read: symbol(read as usize),
+                };
+            };
+            tape[head] = write;
+            state = next as usize;
+            steps += 1;
+            match dir {
+                Dir::Left => {
+                    if head == 0 {
+                        // grow by the current length so moves stay amortized constant time
+                        let len = tape.len();
+                        tape.splice(0..0, std::iter::repeat_n(0, len));
+                        head = len;
+                    }
+                    head -= 1;
+                }
+                Dir::Right => {
+                    head += 1;
+                    if head == tape.len() {
+                        tape.resize(tape.len() * 2, 0);
+                    }
+                }
+                Dir::Stay => {}
+            }
+        };
+
+        Report {
+            steps,
+            ones: tape.iter().filter(|&&c| c != 0).count(),
+            state: self::state(state),
+            stop,
+        }
+    }
+}
+
+/// Reads a machine in the standard busy beaver notation, see [`Table`].
+/// The program gets a run from `A` on a blank tape.
+pub fn parse(notation: &str) -> Result<Program<'static>, BbErr> {
+    Ok(Table::parse(notation)?.program())
+}
+
+#[derive(Debug)]
+pub enum ExportErr<'c> {
+    TooManyStates(usize),
+    TooManySymbols(usize),
+    /// the notation has no stay direction
+    Stay(&'c Instr<&'c str, &'c str>),
+}
+
+impl<'c> Error for ExportErr<'c> {}
+
+impl<'c> fmt::Display for ExportErr<'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportErr::TooManyStates(n) => write!(
+                f,
+                "{n} states do not fit, at most {} states are supported",
+                STATES.len() - 1
+            ),
+            ExportErr::TooManySymbols(n) => write!(
+                f,
+                "{n} symbols do not fit, at most {} symbols are supported",
+                SYMBOLS.len()
+            ),
+            ExportErr::Stay(instr) => write!(f, "`{instr}` stays, which cannot be written"),
+        }
+    }
+}
+
+/// Writes the transition table in the standard busy beaver notation.
+/// `start` becomes `A` and the other states get the next letters in order of appearance,
+/// the blank becomes `0` and the other symbols the next digits, and halt states become `Z`.
+pub fn export<'p, 'c>(program: &'p Program<'c>, start: &'c str) -> Result<String, ExportErr<'p>> {
+    let blank = program
+        .runs
+        .first()
+        .and_then(|r| r.tape.last())
+        .or(program.spec.as_ref().map(|s| &s.blank))
+        .copied();
+    let is_halt = |s: &str| program.halt_syms.contains(&s);
+
+    let mut states = vec![start];
+    let mut syms: Vec<&str> = blank.into_iter().collect();
+    for instr in program.program.iter() {
+        if instr.dir == Dir::Stay {
+            return Err(ExportErr::Stay(instr));
+        }
+        for s in [instr.state, instr.next_state] {
+            if !is_halt(s) && !states.contains(&s) {
+                states.push(s);
+            }
+        }
+        for s in [instr.read, instr.write] {
+            if !syms.contains(&s) {
+                syms.push(s);
+            }
+        }
+    }
+    if states.len() >= STATES.len() {
+        return Err(ExportErr::TooManyStates(states.len()));
+    }
+    if syms.len() > SYMBOLS.len() {
+        return Err(ExportErr::TooManySymbols(syms.len()));
+    }
+    let halt = state(STATES.len() - 1);
+
+    let mut out = String::new();
+    for (i, &s) in states.iter().enumerate() {
+        if i > 0 {
+            out.push('_');
+        }
+        for &sym in syms.iter() {
+            match program.lookup(s, sym) {
+                Some(instr) => {
+                    let write = syms.iter().position(|&w| w == instr.write).unwrap();
+                    let next = match is_halt(instr.next_state) {
+                        true => halt,
+                        false => state(states.iter().position(|&n| n == instr.next_state).unwrap()),
+                    };
+                    let dir = match instr.dir {
+                        Dir::Left => 'L',
+                        _ => 'R',
+                    };
+                    out += &format!("{}{dir}{next}", symbol(write));
+                }
+                None => out += "---",
+            }
+        }
+    }
+    Ok(out)
+}
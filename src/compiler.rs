@@ -1,5 +1,6 @@
 // ** UNDER DEVELOPMENT **
 
+use crate::parser::{Dir, Instr};
 use std::collections::HashMap;
 
 fn asm_static_buf() {
@@ -21,11 +22,43 @@ fn write_to_static_buf(addr: &str, val: &[char]) {
     }
 }
 
-fn asm_exit() {
+/// fills the cells after the initial tape with the blank, the last cell of the initial tape
+fn asm_fill_blank(len: usize, blank: u8) {
+    println!(
+        "    ; fill_blank
+    mov rdi, tape + {len}
+    mov rcx, {rest}
+    mov al, {blank}
+    rep stosb",
+        rest = 256 - len
+    );
+}
+
+/// the code of one rule, it expects the cell under the head in `al` and its index in `rbx`
+/// and jumps to `skip` when the cell is not the read symbol of the rule
+fn asm_rule(instr: &Instr<&str, &str>, next: usize, skip: &str) -> String {
+    let mv = match instr.dir {
+        Dir::Left => "    dec byte [head]\n",
+        Dir::Right => "    inc byte [head]\n",
+        Dir::Stay => "",
+    };
+    format!(
+        "    ; {instr}
+    cmp al, {read}
+    jne {skip}
+    mov byte [tape + rbx], {write}
+{mv}    jmp state_{next}
+",
+        read = instr.read.as_bytes()[0],
+        write = instr.write.as_bytes()[0],
+    )
+}
+
+fn asm_exit(code: u8) {
     println!(
         "    ; exit
     mov rax, 60
-    mov rdi, 0
+    mov rdi, {code}
     syscall"
     );
 }
@@ -113,7 +146,8 @@ tape_print:
 
         println!(
             "
-    mov ecx, head
+    movzx ecx, byte [head]
+    lea ecx, [ecx*2+2]
     mov rax, {after_nl}
 l1:
     mov byte [print_buf+rax], ' '
@@ -136,6 +170,7 @@ l1:
         self.asm_str_name("]", "ket");
         self.asm_str_name("->", "rightarrow");
         self.asm_str_name("<-", "leftarrow");
+        self.asm_str_name("-", "stay");
         self.asm_str_name("^", "halt");
         self.asm_str_name(" ", "space");
         self.asm_str_name("\n", "nl");
@@ -156,13 +191,14 @@ l1:
         println!("section '.text' executable");
         println!("public _start");
 
-        println!("_start:");
-        println!("    mov byte [head], 3");
-
         assert!(program.runs.len() == 1);
         let run = &program.runs[0];
+
+        println!("_start:");
+        println!("    mov byte [head], {}", run.head);
         assert!(
-            run.tape.iter().all(|s| s.len() == 1),
+            run.tape.iter().all(|s| s.len() == 1)
+                && program.used_rules().all(|i| i.read.len() == 1 && i.write.len() == 1),
             "only one char symbols are support for asm target"
         );
 
@@ -173,18 +209,90 @@ l1:
             .collect::<Vec<char>>();
 
         write_to_static_buf("tape", &tape);
+        asm_fill_blank(tape.len(), run.tape[run.tape.len() - 1].as_bytes()[0]);
         self.asm_print_addr("tape", tape.len());
         self.asm_print("\n");
 
+        // states are numbered in order of appearance, starting with the state of the run
+        let mut states = vec![run.state];
+        for instr in program.used_rules() {
+            for s in [instr.state, instr.next_state] {
+                if !states.contains(&s) {
+                    states.push(s);
+                }
+            }
+        }
+        let id = |s: &str| states.iter().position(|&t| t == s).unwrap();
+        println!("    jmp state_0");
+        for (i, &state) in states.iter().enumerate() {
+            println!("state_{i}: ; {state}");
+            if program.halt_syms.contains(&state) {
+                println!("    jmp halted");
+                continue;
+            }
+            println!("    movzx rbx, byte [head]");
+            println!("    mov al, byte [tape + rbx]");
+            let rules = program.used_rules().filter(|instr| instr.state == state);
+            for (j, instr) in rules.enumerate() {
+                let skip = format!("state_{i}_{j}");
+                print!("{}", asm_rule(instr, id(instr.next_state), &skip));
+                println!("{skip}:");
+            }
+            println!("    jmp missing");
+        }
+
+        println!("halted:");
         println!("    call tape_print");
         self.asm_print_addr("print_buf",  ((tape.len() + 1) * 2 + 1) * 2);
 
         self.asm_print("\n");
 
-        // self.asm_print(" -- HALT -- ");
+        self.asm_print(" -- HALT -- ");
+        self.asm_print("\n");
+
+        asm_exit(0);
 
-        asm_exit();
+        // a missing transition exits like `turir run` does
+        println!("missing:");
+        asm_exit(2);
 
         self.tape_print(tape.len());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instr(dir: Dir) -> Instr<&'static str, &'static str> {
+        Instr {
+            state: "I",
+            read: "1",
+            write: "0",
+            dir,
+            next_state: "H",
+        }
+    }
+
+    #[test]
+    fn rule_moves_head() {
+        let right = asm_rule(&instr(Dir::Right), 1, "skip");
+        assert!(right.contains("inc byte [head]"), "{right}");
+        let left = asm_rule(&instr(Dir::Left), 1, "skip");
+        assert!(left.contains("dec byte [head]"), "{left}");
+    }
+
+    #[test]
+    fn stay_rule_keeps_head() {
+        let asm = asm_rule(&instr(Dir::Stay), 1, "skip");
+        assert_eq!(
+            asm,
+            "    ; I 1 0 - H
+    cmp al, 49
+    jne skip
+    mov byte [tape + rbx], 48
+    jmp state_1
+"
+        );
+    }
+}
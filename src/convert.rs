@@ -0,0 +1,448 @@
+use crate::bb::Table;
+use crate::json::{self, Str, Strs, SyntaxErr, Value};
+use crate::parser::{BbRunCmd, Dir, ExpectCmd, Instr, Program, RunCmd, SpecCmd};
+use std::error::Error;
+use std::fmt;
+
+/// the parts of a program are written as single line JSON objects, shared by the JSON and YAML output
+fn write_spec(f: &mut fmt::Formatter, spec: &SpecCmd) -> fmt::Result {
+    write!(
+        f,
+        r#"{{"alphabet":{},"max_len":{},"state":{},"blank":{}}}"#,
+        Strs(&spec.alphabet),
+        spec.max_len,
+        Str(spec.state),
+        Str(spec.blank)
+    )
+}
+
+fn write_run(f: &mut fmt::Formatter, run: &RunCmd) -> fmt::Result {
+    write!(
+        f,
+        r#"{{"tape":{},"state":{}"#,
+        Strs(&run.tape),
+        Str(run.state)
+    )?;
+    if run.head > 0 {
+        write!(f, r#","head":{}"#, run.head)?;
+    }
+    if let Some(expect) = &run.expect {
+        write!(
+            f,
+            r#","expect":{{"tape":{},"state":{}"#,
+            Strs(&expect.tape),
+            Str(expect.state)
+        )?;
+        if let Some(head) = expect.head {
+            write!(f, r#","head":{head}"#)?;
+        }
+        if let Some(steps) = expect.steps {
+            write!(f, r#","steps":{steps}"#)?;
+        }
+        write!(f, "}}")?;
+    }
+    write!(f, "}}")
+}
+
+fn write_rule(f: &mut fmt::Formatter, instr: &Instr<&str, &str>) -> fmt::Result {
+    write!(
+        f,
+        r#"{{"state":{},"read":{},"write":{},"dir":"{}","next_state":{}}}"#,
+        Str(instr.state),
+        Str(instr.read),
+        Str(instr.write),
+        instr.dir.name(),
+        Str(instr.next_state)
+    )
+}
+
+/// Displays a program as a JSON document with its expanded transition table.
+/// Sets, macros and includes do not appear in the output, like with the `.tur` output.
+pub struct Json<'p, 'c>(pub &'p Program<'c>);
+
+impl<'p, 'c> fmt::Display for Json<'p, 'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let program = self.0;
+
+        writeln!(f, "{{")?;
+        writeln!(f, r#"  "halt": {},"#, Strs(&program.halt_syms))?;
+        if !program.accept_syms.is_empty() {
+            writeln!(f, r#"  "accept": {},"#, Strs(&program.accept_syms))?;
+        }
+        if !program.reject_syms.is_empty() {
+            writeln!(f, r#"  "reject": {},"#, Strs(&program.reject_syms))?;
+        }
+        if let Some(alphabet) = &program.alphabet {
+            writeln!(f, r#"  "alphabet": {},"#, Strs(alphabet))?;
+        }
+        if let Some(input) = &program.input {
+            writeln!(f, r#"  "input": {},"#, Strs(input))?;
+        }
+        if let Some(spec) = &program.spec {
+            write!(f, r#"  "spec": "#)?;
+            write_spec(f, spec)?;
+            writeln!(f, ",")?;
+        }
+        write!(f, r#"  "runs": ["#)?;
+        for (i, run) in program.runs.iter().enumerate() {
+            write!(f, "{}\n    ", if i > 0 { "," } else { "" })?;
+            write_run(f, run)?;
+        }
+        if !program.runs.is_empty() {
+            write!(f, "\n  ")?;
+        }
+        writeln!(f, "],")?;
+        if !program.bb_runs.is_empty() {
+            let notations: Vec<&str> = program.bb_runs.iter().map(|r| r.notation).collect();
+            writeln!(f, r#"  "bb": {},"#, Strs(&notations))?;
+        }
+        write!(f, r#"  "rules": ["#)?;
+        for (i, instr) in program.program.iter().enumerate() {
+            write!(f, "{}\n    ", if i > 0 { "," } else { "" })?;
+            write_rule(f, instr)?;
+        }
+        if !program.program.is_empty() {
+            write!(f, "\n  ")?;
+        }
+        writeln!(f, "]")?;
+        writeln!(f, "}}")
+    }
+}
+
+/// Displays a program as a YAML document with the same layout as [`Json`],
+/// every entry is written as a flow mapping.
+pub struct Yaml<'p, 'c>(pub &'p Program<'c>);
+
+impl<'p, 'c> fmt::Display for Yaml<'p, 'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let program = self.0;
+
+        writeln!(f, "halt: {}", Strs(&program.halt_syms))?;
+        if !program.accept_syms.is_empty() {
+            writeln!(f, "accept: {}", Strs(&program.accept_syms))?;
+        }
+        if !program.reject_syms.is_empty() {
+            writeln!(f, "reject: {}", Strs(&program.reject_syms))?;
+        }
+        if let Some(alphabet) = &program.alphabet {
+            writeln!(f, "alphabet: {}", Strs(alphabet))?;
+        }
+        if let Some(input) = &program.input {
+            writeln!(f, "input: {}", Strs(input))?;
+        }
+        if let Some(spec) = &program.spec {
+            write!(f, "spec: ")?;
+            write_spec(f, spec)?;
+            writeln!(f)?;
+        }
+        match program.runs.is_empty() {
+            true => writeln!(f, "runs: []")?,
+            false => writeln!(f, "runs:")?,
+        }
+        for run in program.runs.iter() {
+            write!(f, "  - ")?;
+            write_run(f, run)?;
+            writeln!(f)?;
+        }
+        if !program.bb_runs.is_empty() {
+            let notations: Vec<&str> = program.bb_runs.iter().map(|r| r.notation).collect();
+            writeln!(f, "bb: {}", Strs(&notations))?;
+        }
+        match program.program.is_empty() {
+            true => writeln!(f, "rules: []")?,
+            false => writeln!(f, "rules:")?,
+        }
+        for instr in program.program.iter() {
+            write!(f, "  - ")?;
+            write_rule(f, instr)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportErr {
+    Syntax(SyntaxErr),
+    /// a value that does not fit the program schema, `path` is like `$.runs[0].tape`
+    Schema {
+        path: String,
+        expected: &'static str,
+    },
+}
+
+impl Error for ImportErr {}
+
+impl fmt::Display for ImportErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportErr::Syntax(err) => write!(f, "{err}"),
+            ImportErr::Schema { path, expected } => write!(f, "{path}: expected {expected}"),
+        }
+    }
+}
+
+fn leak(s: &str) -> &'static str {
+    Box::leak(s.to_owned().into_boxed_str())
+}
+
+fn schema<T>(path: &str, expected: &'static str) -> Result<T, ImportErr> {
+    Err(ImportErr::Schema {
+        path: path.to_owned(),
+        expected,
+    })
+}
+
+/// a present and non-null field
+fn field<'v>(value: &'v Value, path: &str, key: &str) -> (Option<&'v Value>, String) {
+    let path = format!("{path}.{key}");
+    match value.get(key) {
+        Some(Value::Null) | None => (None, path),
+        v => (v, path),
+    }
+}
+
+fn string(value: &Value, path: &str, key: &str) -> Result<&'static str, ImportErr> {
+    match field(value, path, key) {
+        (Some(Value::Str(s)), _) => Ok(leak(s)),
+        (_, path) => schema(&path, "a string"),
+    }
+}
+
+fn strings(value: &Value, path: &str, key: &str) -> Result<Vec<&'static str>, ImportErr> {
+    let (Some(Value::Arr(items)), path) = field(value, path, key) else {
+        return schema(&format!("{path}.{key}"), "an array of strings");
+    };
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| match item {
+            Value::Str(s) => Ok(leak(s)),
+            _ => schema(&format!("{path}[{i}]"), "a string"),
+        })
+        .collect()
+}
+
+fn number(value: &Value, path: &str, key: &str) -> Result<Option<usize>, ImportErr> {
+    match field(value, path, key) {
+        (None, _) => Ok(None),
+        (Some(Value::Num(n)), _) if *n >= 0.0 && n.fract() == 0.0 => Ok(Some(*n as usize)),
+        (_, path) => schema(&path, "a non-negative integer"),
+    }
+}
+
+fn object<'v>(value: &'v Value, path: &str) -> Result<&'v Value, ImportErr> {
+    match value {
+        Value::Obj(_) => Ok(value),
+        _ => schema(path, "an object"),
+    }
+}
+
+fn array<'v>(value: &'v Value, path: &str, key: &str) -> Result<&'v [Value], ImportErr> {
+    match field(value, path, key) {
+        (None, _) => Ok(&[]),
+        (Some(Value::Arr(items)), _) => Ok(items),
+        (_, path) => schema(&path, "an array"),
+    }
+}
+
+fn run_cmd(value: &Value, path: &str) -> Result<RunCmd<'static>, ImportErr> {
+    let value = object(value, path)?;
+    let tape = strings(value, path, "tape")?;
+    if tape.is_empty() {
+        return schema(&format!("{path}.tape"), "a non-empty array of strings");
+    }
+    let expect = match field(value, path, "expect") {
+        (None, _) => None,
+        (Some(expect), path) => {
+            let expect = object(expect, &path)?;
+            Some(ExpectCmd {
+                tape: strings(expect, &path, "tape")?,
+                state: string(expect, &path, "state")?,
+                head: number(expect, &path, "head")?,
+                steps: number(expect, &path, "steps")?,
+            })
+        }
+    };
+    let head = number(value, path, "head")?.unwrap_or(0);
+    if head >= tape.len() {
+        return schema(&format!("{path}.head"), "a cell of the tape");
+    }
+    Ok(RunCmd {
+        tape,
+        head,
+        state: string(value, path, "state")?,
+        expect,
+    })
+}
+
+fn rule(value: &Value, path: &str) -> Result<Instr<&'static str, &'static str>, ImportErr> {
+    let value = object(value, path)?;
+    let dir = match field(value, path, "dir") {
+        (Some(Value::Str(dir)), path) => match Dir::from_name(dir) {
+            Some(dir) => dir,
+            None => return schema(&path, "left, right or stay"),
+        },
+        (_, path) => return schema(&path, "left, right or stay"),
+    };
+    Ok(Instr {
+        state: string(value, path, "state")?,
+        read: string(value, path, "read")?,
+        write: string(value, path, "write")?,
+        dir,
+        next_state: string(value, path, "next_state")?,
+    })
+}
+
+/// Reads a program written by [`Json`]. The rules form the transition table as is,
+/// every string is leaked so the program can outlive `src`.
+pub fn from_json(src: &str) -> Result<Program<'static>, ImportErr> {
+    let root = json::parse(src).map_err(ImportErr::Syntax)?;
+    let root = object(&root, "$")?;
+
+    let mut halt_syms = match field(root, "$", "halt") {
+        (None, _) => Vec::new(),
+        _ => strings(root, "$", "halt")?,
+    };
+    let [accept_syms, reject_syms] = ["accept", "reject"].map(|key| match field(root, "$", key) {
+        (None, _) => Ok(Vec::new()),
+        _ => strings(root, "$", key),
+    });
+    let (accept_syms, reject_syms) = (accept_syms?, reject_syms?);
+    // accept and reject states are halt states too
+    for &state in accept_syms.iter().chain(reject_syms.iter()) {
+        if !halt_syms.contains(&state) {
+            halt_syms.push(state);
+        }
+    }
+
+    let spec = match field(root, "$", "spec") {
+        (None, _) => None,
+        (Some(spec), path) => {
+            let spec = object(spec, &path)?;
+            let alphabet = strings(spec, &path, "alphabet")?;
+            let Some(max_len) = number(spec, &path, "max_len")? else {
+                return schema(&format!("{path}.max_len"), "a non-negative integer");
+            };
+            let blank = match field(spec, &path, "blank") {
+                (None, _) => match alphabet.first() {
+                    Some(&blank) => blank,
+                    None => return schema(&format!("{path}.blank"), "a string"),
+                },
+                _ => string(spec, &path, "blank")?,
+            };
+            Some(SpecCmd {
+                alphabet,
+                max_len,
+                state: string(spec, &path, "state")?,
+                blank,
+            })
+        }
+    };
+
+    let runs = array(root, "$", "runs")?
+        .iter()
+        .enumerate()
+        .map(|(i, run)| run_cmd(run, &format!("$.runs[{i}]")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let program = array(root, "$", "rules")?
+        .iter()
+        .enumerate()
+        .map(|(i, instr)| rule(instr, &format!("$.rules[{i}]")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let bb_runs = match field(root, "$", "bb") {
+        (None, _) => Vec::new(),
+        (_, path) => strings(root, "$", "bb")?
+            .into_iter()
+            .enumerate()
+            .map(|(i, notation)| match Table::parse(notation) {
+                // the JSON layout keeps them apart from the tape runs, they run after them
+                Ok(table) => Ok(BbRunCmd {
+                    notation,
+                    table,
+                    at: runs.len(),
+                }),
+                Err(_) => schema(&format!("{path}[{i}]"), "a busy beaver machine like 1RB1LB_1LA1RZ"),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+
+    let alphabet = match field(root, "$", "alphabet") {
+        (None, _) => None,
+        _ => Some(strings(root, "$", "alphabet")?),
+    };
+    let input = match field(root, "$", "input") {
+        (None, _) => None,
+        _ => Some(strings(root, "$", "input")?),
+    };
+
+    let mut program = Program::from_table(runs, halt_syms, spec, program);
+    program.bb_runs = bb_runs;
+    program.accept_syms = accept_syms;
+    program.reject_syms = reject_syms;
+    program.alphabet = alphabet;
+    program.input = input;
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_source;
+
+    /// exports `src` as JSON, imports it again and checks that nothing is lost on the way
+    fn round_trip(src: &'static str) {
+        let program = parse_source(src.as_bytes(), "test.tur").unwrap();
+        let json = Json(&program).to_string();
+        let imported = from_json(&json).unwrap();
+        assert_eq!(imported.to_string(), program.to_string());
+        assert_eq!(Json(&imported).to_string(), json);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        round_trip(
+            "#halt H\n\
+             #run [1 0 >1 1] I\n\
+             #run \"abba#\" I\n\
+             I 1 0 -> I\n\
+             I 0 1 <- I\n\
+             I a b - H\n",
+        );
+    }
+
+    #[test]
+    fn json_round_trip_accept_reject() {
+        round_trip(
+            "#accept Y\n\
+             #reject N\n\
+             #alphabet 0 1 _\n\
+             #input 0 1\n\
+             #spec [0 1] 3 I _\n\
+             #run [0 1 _] I\n\
+             I 0 0 -> I\n\
+             I 1 1 -> N\n\
+             I _ _ - Y\n",
+        );
+    }
+
+    #[test]
+    fn json_round_trip_quoted_symbols() {
+        round_trip(
+            "#halt H\n\
+             #run ['' '\\'' '\"' '\\\\' '\\n' '\\u{d}' '#' ' '] I\n\
+             I '' '\\u{d}' -> I\n\
+             I '\\'' '\\n' - H\n",
+        );
+    }
+
+    #[test]
+    fn json_errors() {
+        assert!(matches!(from_json("{\"runs\": ["), Err(ImportErr::Syntax(_))));
+        let Err(ImportErr::Schema { path, .. }) = from_json("{\"halt\": [\"H\", 1]}") else {
+            panic!("a number is not a state");
+        };
+        assert_eq!(path, "$.halt[1]");
+    }
+}
@@ -0,0 +1,153 @@
+use crate::interp::{Machine, Stop};
+use crate::parser::{self, Program, RunCmd};
+use std::io::{self, Write};
+
+/// pixels per tape cell
+const CELL: usize = 8;
+
+/// the blank symbol is always white, the others get these in order of first appearance
+const PALETTE: [(u8, u8, u8); 10] = [
+    (31, 119, 180),
+    (255, 127, 14),
+    (44, 160, 44),
+    (214, 39, 40),
+    (148, 103, 189),
+    (140, 86, 75),
+    (227, 119, 194),
+    (127, 127, 127),
+    (188, 189, 34),
+    (23, 190, 207),
+];
+const BLANK: (u8, u8, u8) = (255, 255, 255);
+const HEAD: (u8, u8, u8) = (0, 0, 0);
+
+struct Row<'c> {
+    tape: Vec<&'c str>,
+    head: usize,
+    offset: usize,
+}
+
+/// Space-time diagram of a run, one row per step and one column per tape cell.
+pub struct Diagram<'c> {
+    rows: Vec<Row<'c>>,
+    syms: Vec<&'c str>,
+    blank: &'c str,
+    /// leftmost and one past the rightmost cell relative to the initial tape
+    cols: (isize, isize),
+}
+
+impl<'c> Diagram<'c> {
+    /// runs the machine and records every configuration it goes through
+    pub fn record(
+        program: &Program<'c>,
+        run: &RunCmd<'c>,
+        max_steps: Option<usize>,
+    ) -> (Diagram<'c>, &'c str, Stop<'c>) {
+        let mut machine = Machine::new(program, run);
+        let mut rows = Vec::new();
+        let stop = loop {
+            rows.push(Row {
+                tape: machine.tape.clone(),
+                head: machine.head,
+                offset: machine.offset,
+            });
+            if max_steps.is_some_and(|max| machine.steps >= max) && !machine.is_halted() {
+                break Stop::StepLimit;
+            }
+            match machine.next_instr() {
+                Ok(instr) => machine.apply(instr),
+                Err(stop) => break stop,
+            }
+        };
+
+        let left = -(machine.offset as isize);
+        let right = rows
+            .iter()
+            .map(|r| r.tape.len().max(r.head + 1) as isize - r.offset as isize)
+            .max()
+            .unwrap_or(0);
+
+        let mut syms = parser::tape_symbols(&program.rules, &program.runs);
+        syms.retain(|&s| s != machine.blank());
+        let diagram = Diagram {
+            rows,
+            syms,
+            blank: machine.blank(),
+            cols: (left, right),
+        };
+        (diagram, machine.state, stop)
+    }
+
+    fn color(&self, sym: &str) -> (u8, u8, u8) {
+        if sym == self.blank {
+            return BLANK;
+        }
+        match self.syms.iter().position(|&s| s == sym) {
+            Some(i) => PALETTE[i % PALETTE.len()],
+            None => PALETTE[PALETTE.len() - 1],
+        }
+    }
+
+    fn width(&self) -> usize {
+        (self.cols.1 - self.cols.0) as usize
+    }
+
+    /// the symbol and whether the head is on it for every column of a row
+    fn cells<'a>(&'a self, row: &'a Row<'c>) -> impl Iterator<Item = (&'c str, bool)> + 'a {
+        (self.cols.0..self.cols.1).map(move |col| {
+            let i = col + row.offset as isize;
+            let sym = match usize::try_from(i) {
+                Ok(i) => row.tape.get(i).copied().unwrap_or(self.blank),
+                Err(_) => self.blank,
+            };
+            (sym, i == row.head as isize)
+        })
+    }
+
+    pub fn write_svg(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(
+            w,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" shape-rendering=\"crispEdges\">",
+            self.width() * CELL,
+            self.rows.len() * CELL
+        )?;
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, (sym, head)) in self.cells(row).enumerate() {
+                let (r, g, b) = self.color(sym);
+                let stroke = match head {
+                    true => " stroke=\"black\" stroke-width=\"2\"",
+                    false => "",
+                };
+                writeln!(
+                    w,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{CELL}\" height=\"{CELL}\" fill=\"#{r:02x}{g:02x}{b:02x}\"{stroke}/>",
+                    x * CELL,
+                    y * CELL
+                )?;
+            }
+        }
+        writeln!(w, "</svg>")
+    }
+
+    /// binary PPM (P6), the head cell gets a black frame
+    pub fn write_ppm(&self, w: &mut impl Write) -> io::Result<()> {
+        let (width, height) = (self.width() * CELL, self.rows.len() * CELL);
+        write!(w, "P6\n{width} {height}\n255\n")?;
+        for row in self.rows.iter() {
+            let cells: Vec<_> = self.cells(row).collect();
+            for py in 0..CELL {
+                for &(sym, head) in cells.iter() {
+                    for px in 0..CELL {
+                        let edge = px == 0 || py == 0 || px == CELL - 1 || py == CELL - 1;
+                        let (r, g, b) = match head && edge {
+                            true => HEAD,
+                            false => self.color(sym),
+                        };
+                        w.write_all(&[r, g, b])?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use crate::interp::{Machine, Stop};
+use crate::parser::{Dir, Program, RunCmd};
+
+/// one side of the tape as blocks of `(symbol, count)`, the block next to the head is the last one
+/// and every cell beyond the first block is blank
+type Side = Vec<(usize, usize)>;
+
+fn push(side: &mut Side, sym: usize, count: usize) {
+    match side.last_mut() {
+        _ if count == 0 => {}
+        Some((s, n)) if *s == sym => *n += count,
+        _ => side.push((sym, count)),
+    }
+}
+
+/// removes `count` cells next to the head, the cells beyond the blocks are blank
+fn drop_cells(side: &mut Side, mut count: usize) {
+    while count > 0 {
+        let Some((_, n)) = side.last_mut() else {
+            return;
+        };
+        if *n > count {
+            *n -= count;
+            return;
+        }
+        count -= *n;
+        side.pop();
+    }
+}
+
+fn pop(side: &mut Side, blank: usize) -> usize {
+    match side.last_mut() {
+        Some((s, n)) => {
+            let sym = *s;
+            *n -= 1;
+            if *n == 0 {
+                side.pop();
+            }
+            sym
+        }
+        None => blank,
+    }
+}
+
+fn intern<'c>(names: &mut Vec<&'c str>, name: &'c str) -> usize {
+    match names.iter().position(|&n| n == name) {
+        Some(i) => i,
+        None => {
+            names.push(name);
+            names.len() - 1
+        }
+    }
+}
+
+/// Runs the machine like [`Machine::run`] with the tape stored as blocks of repeated symbols.
+/// When a rule moves in a direction and keeps the state, the head sweeps over the whole
+/// block of its read symbol in one go, so the number of iterations depends on the number of
+/// blocks the machine goes through rather than the number of steps.
+/// The returned machine holds the same configuration the naive interpreter ends with.
+pub fn run<'p, 'c>(
+    program: &'p Program<'c>,
+    run: &RunCmd<'c>,
+    max_steps: Option<usize>,
+) -> (Machine<'p, 'c>, Stop<'c>) {
+    let mut machine = Machine::new(program, run);
+
+    let (mut states, mut syms) = (Vec::new(), Vec::new());
+    let blank = intern(&mut syms, machine.blank());
+    let mut state = intern(&mut states, run.state);
+    let tape: Vec<usize> = run.tape.iter().map(|&s| intern(&mut syms, s)).collect();
+    let rules: Vec<_> = program
+        .used_rules()
+        .map(|i| {
+            let (state, next) = (intern(&mut states, i.state), intern(&mut states, i.next_state));
+            let (read, write) = (intern(&mut syms, i.read), intern(&mut syms, i.write));
+            (state, read, write, i.dir, next)
+        })
+        .collect();
+    let halt: Vec<bool> = states.iter().map(|s| program.halt_syms.contains(s)).collect();
+    // `(write, dir, next)` indexed by `state * syms.len() + read`
+    let mut table = vec![None; states.len() * syms.len()];
+    for &(state, read, write, dir, next) in rules.iter() {
+        table[state * syms.len() + read] = Some((write, dir, next));
+    }
+
+    let (mut left, mut right): (Side, Side) = (Vec::new(), Vec::new());
+    for &sym in tape[..run.head].iter() {
+        push(&mut left, sym, 1);
+    }
+    for &sym in tape[run.head + 1..].iter().rev() {
+        push(&mut right, sym, 1);
+    }
+    let mut cur = tape[run.head];
+    // positions are relative to the first cell of the initial tape,
+    // `lo..=hi` is the part of the tape the naive interpreter would have
+    let (mut pos, mut lo, mut hi) = (run.head as isize, 0isize, tape.len() as isize - 1);
+    let mut steps = 0usize;
+
+    let stop = loop {
+        if halt[state] {
+            break Stop::Halted;
+        }
+        if max_steps.is_some_and(|max| steps >= max) {
+            break Stop::StepLimit;
+        }
+        let remaining = max_steps.map_or(usize::MAX, |max| max - steps);
+        // an endless sweep jumps to the step limit, without one it goes on like the naive interpreter
+        let endless = match max_steps {
+            Some(_) => remaining,
+            None => 1,
+        };
+        let Some((write, dir, next)) = table[state * syms.len() + cur] else {
+            break Stop::Missing {
+                state: states[state],
+                read: syms[cur],
+            };
+        };
+
+        let (behind, ahead, sign) = match dir {
+            Dir::Left => (&mut right, &mut left, -1),
+            Dir::Right => (&mut left, &mut right, 1),
+            Dir::Stay => {
+                // keeps writing the same symbol in the same state forever
+                steps += match next == state && write == cur {
+                    true => endless,
+                    false => 1,
+                };
+                cur = write;
+                state = next;
+                continue;
+            }
+        };
+
+        // the cells the rule applies to in a row, the read cell and the cells of its symbol ahead
+        let mut count = 1;
+        if next == state {
+            count = match ahead.last() {
+                Some(&(s, _)) if s == cur && ahead.len() == 1 && cur == blank => endless,
+                Some(&(s, n)) if s == cur => (n + 1).min(remaining),
+                None if cur == blank => endless,
+                _ => 1,
+            };
+        }
+
+        push(behind, write, count);
+        drop_cells(ahead, count - 1);
+        cur = pop(ahead, blank);
+        pos += sign * count as isize;
+        state = next;
+        steps += count;
+
+        lo = lo.min(pos);
+        // a halted machine does not extend its tape to the right
+        if pos > hi && !halt[state] {
+            hi = pos;
+        }
+    };
+
+    // lay out the blocks over `lo..=hi`
+    let mut cells = vec![syms[blank]; (hi - lo + 1) as usize];
+    let index = |p: isize| (p - lo) as usize;
+    let mut p = pos;
+    for &(sym, n) in left.iter().rev() {
+        for _ in 0..n {
+            p -= 1;
+            cells[index(p)] = syms[sym];
+        }
+    }
+    if pos <= hi {
+        cells[index(pos)] = syms[cur];
+    }
+    let mut p = pos;
+    for &(sym, n) in right.iter().rev() {
+        for _ in 0..n {
+            p += 1;
+            if p > hi {
+                break;
+            }
+            cells[index(p)] = syms[sym];
+        }
+    }
+
+    machine.tape = cells;
+    machine.head = index(pos);
+    machine.state = states[state];
+    machine.steps = steps;
+    machine.offset = (-lo) as usize;
+    (machine, stop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_source;
+
+    /// runs every `#run` of `src` with both engines and checks they end in the same configuration
+    fn same_as_interp(src: &'static str, max_steps: Option<usize>) -> Vec<Stop<'static>> {
+        let program = parse_source(src.as_bytes(), "test.tur").unwrap();
+        let program = Box::leak(Box::new(program));
+        let mut stops = Vec::new();
+        for run in program.runs.iter() {
+            let mut naive = Machine::new(program, run);
+            let naive_stop = naive.run(max_steps);
+            let (fast, fast_stop) = super::run(program, run, max_steps);
+            assert_eq!(fast_stop, naive_stop, "{run}");
+            assert_eq!(fast.tape, naive.tape, "{run}");
+            assert_eq!(fast.head, naive.head, "{run}");
+            assert_eq!(fast.offset, naive.offset, "{run}");
+            assert_eq!(fast.state, naive.state, "{run}");
+            assert_eq!(fast.steps, naive.steps, "{run}");
+            stops.push(fast_stop);
+        }
+        stops
+    }
+
+    #[test]
+    fn halts_like_interp() {
+        // unary increment sweeping over long blocks in both directions
+        let src = "#halt H\n\
+                   #run [1 1 1 1 1 0] S\n\
+                   #run [0] S\n\
+                   #run [1 1 >1 0 1 1 0] S\n\
+                   S 1 1 -> S\n\
+                   S 0 1 <- B\n\
+                   B 1 1 <- B\n\
+                   B 0 0 -> H\n";
+        assert_eq!(same_as_interp(src, None), [Stop::Halted; 3]);
+    }
+
+    #[test]
+    fn missing_transition_like_interp() {
+        let src = "#halt H\n\
+                   #run [1 1 1 x 0] S\n\
+                   S 1 0 -> S\n\
+                   S 0 0 -> H\n";
+        let stops = same_as_interp(src, None);
+        assert_eq!(stops, [Stop::Missing { state: "S", read: "x" }]);
+    }
+
+    #[test]
+    fn step_limit_like_interp() {
+        // sweeps over an ever growing block of 1s, so the fast engine skips most of the steps
+        let src = "#halt H\n\
+                   #run [0] R\n\
+                   R 0 1 <- L\n\
+                   R 1 1 -> R\n\
+                   L 0 1 -> R\n\
+                   L 1 1 <- L\n";
+        for max_steps in [0, 1, 7, 100, 12345] {
+            assert_eq!(same_as_interp(src, Some(max_steps)), [Stop::StepLimit]);
+        }
+    }
+}
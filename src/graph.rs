@@ -0,0 +1,90 @@
+use crate::parser::Program;
+use std::fmt;
+
+/// a string in a DOT quoted id
+struct Escaped<'a>(&'a str);
+
+impl<'a> fmt::Display for Escaped<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '"' | '\\' => write!(f, "\\{c}")?,
+                '\n' => write!(f, "\\n")?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Displays the state diagram of a program in Graphviz DOT syntax.
+/// Rules between the same two states are merged into a single edge with one label line per rule.
+pub struct Dot<'p, 'c>(pub &'p Program<'c>);
+
+impl<'p, 'c> fmt::Display for Dot<'p, 'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let program = self.0;
+
+        let mut states: Vec<&str> = Vec::new();
+        let mut edges: Vec<(&str, &str, Vec<String>)> = Vec::new();
+        for instr in program.used_rules() {
+            for s in [instr.state, instr.next_state] {
+                if !states.contains(&s) {
+                    states.push(s);
+                }
+            }
+            let label = format!("{}/{},{}", instr.read, instr.write, instr.dir);
+            match edges
+                .iter_mut()
+                .find(|e| e.0 == instr.state && e.1 == instr.next_state)
+            {
+                Some(edge) => edge.2.push(label),
+                None => edges.push((instr.state, instr.next_state, vec![label])),
+            }
+        }
+        let starts = program
+            .runs
+            .iter()
+            .map(|r| r.state)
+            .chain(program.spec.iter().map(|s| s.state));
+        let mut start_states: Vec<&str> = Vec::new();
+        for s in starts {
+            if !start_states.contains(&s) {
+                start_states.push(s);
+            }
+            if !states.contains(&s) {
+                states.push(s);
+            }
+        }
+        for &s in program.halt_syms.iter() {
+            if !states.contains(&s) {
+                states.push(s);
+            }
+        }
+
+        writeln!(f, "digraph turir {{")?;
+        writeln!(f, "    rankdir=LR;")?;
+        writeln!(f, "    node [shape=circle];")?;
+        for s in states.iter() {
+            let shape = match program.halt_syms.contains(s) {
+                true => "doublecircle",
+                false => "circle",
+            };
+            writeln!(f, "    \"{}\" [shape={shape}];", Escaped(s))?;
+        }
+        for (i, s) in start_states.iter().enumerate() {
+            writeln!(f, "    __start{i} [shape=point];")?;
+            writeln!(f, "    __start{i} -> \"{}\";", Escaped(s))?;
+        }
+        for (from, to, labels) in edges.iter() {
+            writeln!(
+                f,
+                "    \"{}\" -> \"{}\" [label=\"{}\"];",
+                Escaped(from),
+                Escaped(to),
+                Escaped(&labels.join("\n"))
+            )?;
+        }
+        writeln!(f, "}}")
+    }
+}
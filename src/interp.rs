@@ -0,0 +1,109 @@
+use crate::parser::{Dir, Instr, Program, RunCmd};
+use std::fmt;
+
+/// why a machine stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop<'c> {
+    Halted,
+    Missing { state: &'c str, read: &'c str },
+    StepLimit,
+}
+
+impl<'c> fmt::Display for Stop<'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Halted => write!(f, "halted"),
+            Stop::Missing { state, read } => {
+                write!(f, "State '{state}' and read '{read}' combination is not defined")
+            }
+            Stop::StepLimit => write!(f, "step limit is reached"),
+        }
+    }
+}
+
+pub struct Machine<'p, 'c> {
+    program: &'p Program<'c>,
+    pub tape: Vec<&'c str>,
+    pub head: usize,
+    pub state: &'c str,
+    pub steps: usize,
+    /// number of cells the tape has grown to the left of the initial tape
+    pub offset: usize,
+    blank: &'c str,
+}
+
+impl<'p, 'c> Machine<'p, 'c> {
+    pub fn new(program: &'p Program<'c>, run: &RunCmd<'c>) -> Self {
+        Self {
+            program,
+            tape: run.tape.clone(),
+            head: run.head,
+            state: run.state,
+            steps: 0,
+            offset: 0,
+            // the tape is extended with its last symbol
+            blank: run.tape.last().expect("tape cannot be empty"),
+        }
+    }
+
+    pub fn blank(&self) -> &'c str {
+        self.blank
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.program.halt_syms.contains(&self.state)
+    }
+
+    pub fn read(&self) -> &'c str {
+        self.tape.get(self.head).copied().unwrap_or(self.blank)
+    }
+
+    /// the instruction that would be applied next
+    pub fn next_instr(&self) -> Result<&'p Instr<&'c str, &'c str>, Stop<'c>> {
+        if self.is_halted() {
+            return Err(Stop::Halted);
+        }
+        let read = self.read();
+        self.program
+            .lookup(self.state, read)
+            .ok_or(Stop::Missing {
+                state: self.state,
+                read,
+            })
+    }
+
+    pub fn apply(&mut self, instr: &Instr<&'c str, &'c str>) {
+        if self.tape.len() <= self.head {
+            self.tape.push(self.blank);
+        }
+        self.tape[self.head] = instr.write;
+        self.state = instr.next_state;
+        self.steps += 1;
+
+        match instr.dir {
+            Dir::Left if self.head == 0 => {
+                self.tape.insert(0, self.blank);
+                self.offset += 1;
+            }
+            Dir::Left => self.head -= 1,
+            Dir::Right => self.head += 1,
+            Dir::Stay => {}
+        }
+        // a halted machine keeps its tape as is, the head may be one past the end
+        if self.tape.len() <= self.head && !self.is_halted() {
+            self.tape.push(self.blank);
+        }
+    }
+
+    pub fn run(&mut self, max_steps: Option<usize>) -> Stop<'c> {
+        loop {
+            if max_steps.is_some_and(|max| self.steps >= max) && !self.is_halted() {
+                return Stop::StepLimit;
+            }
+            match self.next_instr() {
+                Ok(instr) => self.apply(instr),
+                Err(stop) => return stop,
+            }
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+
+/// displays a string as a quoted JSON string
+pub struct Str<'a>(pub &'a str);
+
+impl<'a> fmt::Display for Str<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+/// displays a list of strings as a JSON array
+pub struct Strs<'a, 'c>(pub &'a [&'c str]);
+
+impl<'a, 'c> fmt::Display for Strs<'a, 'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, s) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", Str(s))?;
+        }
+        write!(f, "]")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Arr(Vec<Value>),
+    /// keys in source order
+    Obj(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Obj(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SyntaxErr {
+    pub msg: &'static str,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for SyntaxErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line + 1, self.col + 1, self.msg)
+    }
+}
+
+struct JsonParser<'s> {
+    src: &'s [u8],
+    cur: usize,
+}
+
+impl<'s> JsonParser<'s> {
+    fn err<T>(&self, msg: &'static str) -> Result<T, SyntaxErr> {
+        let before = &self.src[..self.cur.min(self.src.len())];
+        let line = before.iter().filter(|&&c| c == b'\n').count();
+        let col = before.iter().rev().take_while(|&&c| c != b'\n').count();
+        Err(SyntaxErr { msg, line, col })
+    }
+
+    fn skip_ws(&mut self) {
+        while self.cur < self.src.len() && self.src[self.cur].is_ascii_whitespace() {
+            self.cur += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_ws();
+        self.src.get(self.cur).copied()
+    }
+
+    fn expect(&mut self, c: u8, msg: &'static str) -> Result<(), SyntaxErr> {
+        match self.peek() {
+            Some(p) if p == c => {
+                self.cur += 1;
+                Ok(())
+            }
+            _ => self.err(msg),
+        }
+    }
+
+    fn literal(&mut self, lit: &str, value: Value) -> Result<Value, SyntaxErr> {
+        if self.src[self.cur..].starts_with(lit.as_bytes()) {
+            self.cur += lit.len();
+            Ok(value)
+        } else {
+            self.err("unknown literal")
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, SyntaxErr> {
+        match self.peek() {
+            None => self.err("unexpected end of input"),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'"') => Ok(Value::Str(self.string()?)),
+            Some(b'[') => {
+                self.cur += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.cur += 1;
+                    return Ok(Value::Arr(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.peek() {
+                        Some(b',') => self.cur += 1,
+                        Some(b']') => {
+                            self.cur += 1;
+                            return Ok(Value::Arr(items));
+                        }
+                        _ => return self.err("expected `,` or `]`"),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.cur += 1;
+                let mut fields = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.cur += 1;
+                    return Ok(Value::Obj(fields));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return self.err("expected a string key");
+                    }
+                    let key = self.string()?;
+                    self.expect(b':', "expected `:`")?;
+                    fields.push((key, self.value()?));
+                    match self.peek() {
+                        Some(b',') => self.cur += 1,
+                        Some(b'}') => {
+                            self.cur += 1;
+                            return Ok(Value::Obj(fields));
+                        }
+                        _ => return self.err("expected `,` or `}`"),
+                    }
+                }
+            }
+            Some(c) if c == b'-' || c.is_ascii_digit() => {
+                let start = self.cur;
+                while self.cur < self.src.len()
+                    && matches!(self.src[self.cur], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+                {
+                    self.cur += 1;
+                }
+                let text = std::str::from_utf8(&self.src[start..self.cur]).unwrap();
+                match text.parse() {
+                    Ok(n) => Ok(Value::Num(n)),
+                    Err(_) => self.err("invalid number"),
+                }
+            }
+            Some(_) => self.err("unexpected character"),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, SyntaxErr> {
+        let hex = self.src.get(self.cur..self.cur + 4);
+        let code = hex
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u32::from_str_radix(h, 16).ok());
+        match code {
+            Some(code) => {
+                self.cur += 4;
+                Ok(code)
+            }
+            None => self.err("invalid unicode escape"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SyntaxErr> {
+        self.cur += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(&c) = self.src.get(self.cur) else {
+                return self.err("unclosed string");
+            };
+            self.cur += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&e) = self.src.get(self.cur) else {
+                        return self.err("unclosed string");
+                    };
+                    self.cur += 1;
+                    let c = match e {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // surrogate pair
+                            if (0xD800..0xDC00).contains(&code)
+                                && self.src[self.cur..].starts_with(b"\\u")
+                            {
+                                self.cur += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00));
+                            }
+                            match char::from_u32(code) {
+                                Some(c) => c,
+                                None => return self.err("invalid unicode escape"),
+                            }
+                        }
+                        _ => return self.err("invalid escape"),
+                    };
+                    bytes.extend(c.to_string().as_bytes());
+                }
+                c => bytes.push(c),
+            }
+        }
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            Err(_) => self.err("invalid UTF-8 in string"),
+        }
+    }
+}
+
+pub fn parse(src: &str) -> Result<Value, SyntaxErr> {
+    let mut parser = JsonParser {
+        src: src.as_bytes(),
+        cur: 0,
+    };
+    let value = parser.value()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(_) => parser.err("trailing characters"),
+    }
+}
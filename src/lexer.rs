@@ -4,11 +4,17 @@ use std::fmt;
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TokenKind {
     Symbol,
+    Str,
     LeftArrow,
     RightArrow,
+    Stay,
+    Wildcard,
+    Same,
     Cmd,
+    SetRef,
     Bra,
     Ket,
+    Head,
     NewLine,
 }
 
@@ -16,11 +22,17 @@ impl TokenKind {
     pub const fn to_str(self) -> &'static str {
         match self {
             TokenKind::Symbol => "Symbol",
+            TokenKind::Str => "String",
             TokenKind::LeftArrow => "<-",
             TokenKind::RightArrow => "->",
+            TokenKind::Stay => "-",
+            TokenKind::Wildcard => "*",
+            TokenKind::Same => "=",
             TokenKind::Cmd => "Cmd",
+            TokenKind::SetRef => "SetRef",
             TokenKind::Bra => "[",
             TokenKind::Ket => "]",
+            TokenKind::Head => ">",
             TokenKind::NewLine => "new line",
         }
     }
@@ -48,7 +60,7 @@ pub struct Token<'c> {
 
 #[derive(Debug)]
 pub struct Lexer<'c> {
-    content: &'c [u8],
+    content: &'c str,
     file: &'static str,
     cur: usize,
     bol: usize,
@@ -56,7 +68,7 @@ pub struct Lexer<'c> {
 }
 
 impl<'c> Lexer<'c> {
-    pub fn new(content: &'c [u8], file: &'static str) -> Self {
+    pub fn new(content: &'c str, file: &'static str) -> Self {
         Self {
             content,
             file,
@@ -72,31 +84,40 @@ pub enum TokenResult<'c> {
     Eof { loc: Loc },
     Unknown { text: &'c str, loc: Loc },
     UnclosedStr { loc: Loc },
+    /// a backslash in a string that is not followed by one of the escaped characters
+    InvalidEscape { text: &'c str, loc: Loc },
     Valid(Token<'c>),
 }
 
-const LITERALS: [(&str, TokenKind); 5] = [
+const LITERALS: [(&str, TokenKind); 9] = [
     ("->", TokenKind::RightArrow),
     ("<-", TokenKind::LeftArrow),
+    ("-", TokenKind::Stay),
+    ("*", TokenKind::Wildcard),
+    ("=", TokenKind::Same),
     ("[", TokenKind::Bra),
     ("]", TokenKind::Ket),
+    (">", TokenKind::Head),
     ("\n", TokenKind::NewLine),
 ];
 
 impl<'c> Lexer<'c> {
     fn text_from_content(&self, start: usize) -> &'c str {
-        unsafe { std::str::from_utf8_unchecked(&self.content[start..self.cur]) }
+        &self.content[start..self.cur]
+    }
+
+    fn byte(&self) -> u8 {
+        self.content.as_bytes()[self.cur]
     }
 
     fn trim_left(&mut self) {
-        while !self.exhausted() && matches!(self.content[self.cur], b'\t' | b'\x0C' | b'\r' | b' ')
-        {
+        while !self.exhausted() && matches!(self.byte(), b'\t' | b'\x0C' | b'\r' | b' ') {
             self.skip_n(1);
         }
     }
 
     fn starts_with(&self, s: &str) -> bool {
-        self.content[self.cur..]
+        self.content.as_bytes()[self.cur..]
             .iter()
             .zip(s.as_bytes())
             .all(|c| c.0 == c.1)
@@ -105,7 +126,7 @@ impl<'c> Lexer<'c> {
     fn skip_n(&mut self, n: usize) {
         assert!(!self.exhausted());
         for _ in 0..n {
-            let c = self.content[self.cur];
+            let c = self.byte();
             self.cur += 1;
             if c == b'\n' {
                 self.bol = self.cur;
@@ -114,6 +135,10 @@ impl<'c> Lexer<'c> {
         }
     }
 
+    pub fn file(&self) -> &'static str {
+        self.file
+    }
+
     pub fn loc(&self) -> Loc {
         Loc {
             file: self.file,
@@ -130,7 +155,7 @@ impl<'c> Lexer<'c> {
     }
 
     fn skip_until(&mut self, c: u8) {
-        while !self.exhausted() && self.content[self.cur] != c {
+        while !self.exhausted() && self.byte() != c {
             self.skip_n(1);
         }
     }
@@ -146,7 +171,7 @@ impl<'c> Lexer<'c> {
         P: Fn(u8) -> bool,
     {
         self.skip_n(1);
-        while !self.exhausted() && pred(self.content[self.cur]) {
+        while !self.exhausted() && pred(self.byte()) {
             self.skip_n(1);
         }
         TokenResult::Valid(Token {
@@ -156,23 +181,42 @@ impl<'c> Lexer<'c> {
         })
     }
 
-    fn extract_string_token(&mut self, start: usize, loc: Loc) -> TokenResult<'c> {
+    /// Extracts the text between two `quote`s on the same line, replacing its escapes.
+    fn extract_string_token(
+        &mut self,
+        quote: u8,
+        kind: TokenKind,
+        start: usize,
+        loc: Loc,
+    ) -> TokenResult<'c> {
+        let mut escaped = false;
+        self.skip_n(1);
         loop {
-            self.skip_n(1);
-            if self.content[self.cur] == b'\n' || self.exhausted() {
+            if self.exhausted() || self.byte() == b'\n' {
                 return TokenResult::UnclosedStr { loc };
             }
-            if self.content[self.cur] == b'\'' {
+            let c = self.byte();
+            if c == quote {
                 break;
             }
+            self.skip_n(1);
+            if c == b'\\' {
+                escaped = true;
+                if self.exhausted() || self.byte() == b'\n' {
+                    return TokenResult::UnclosedStr { loc };
+                }
+                self.skip_n(1);
+            }
         }
-        let tok = TokenResult::Valid(Token {
-            kind: TokenKind::Symbol,
-            text: self.text_from_content(start + 1),
-            loc,
-        });
+        let mut text = self.text_from_content(start + 1);
         self.skip_n(1);
-        tok
+        if escaped {
+            text = match unescape(text) {
+                Ok(s) => Box::leak(s.into_boxed_str()),
+                Err(text) => return TokenResult::InvalidEscape { text, loc },
+            };
+        }
+        TokenResult::Valid(Token { kind, text, loc })
     }
 
     pub fn next_token(&mut self) -> TokenResult<'c> {
@@ -188,15 +232,25 @@ impl<'c> Lexer<'c> {
             self.skip_until(b'\n');
         }
 
-        if self.content[self.cur] == b'\'' {
-            return self.extract_string_token(start, loc);
+        if self.byte() == b'\'' {
+            return self.extract_string_token(b'\'', TokenKind::Symbol, start, loc);
+        }
+
+        if self.byte() == b'"' {
+            return self.extract_string_token(b'"', TokenKind::Str, start, loc);
         }
 
-        if self.content[self.cur] == b'#' {
+        if self.byte() == b'#' {
             return self.extract_token(is_symbol, TokenKind::Cmd, start, loc);
         }
 
-        if is_symbol(self.content[self.cur]) {
+        // a `$` that is not followed by a set name is a plain symbol
+        let named = self.content.as_bytes().get(self.cur + 1).is_some_and(|&c| is_symbol(c));
+        if self.byte() == b'$' && named {
+            return self.extract_token(is_symbol, TokenKind::SetRef, start, loc);
+        }
+
+        if is_symbol(self.byte()) {
             return self.extract_token(is_symbol, TokenKind::Symbol, start, loc);
         }
 
@@ -223,9 +277,192 @@ impl<'c> Lexer<'c> {
     }
 }
 
+/// Replaces the escapes of a quoted symbol or string, or returns the first invalid one:
+/// `\'`, `\"`, `\\`, `\n`, `\t` and `\u{<HEX>}` for any character.
+fn unescape(text: &str) -> Result<String, &str> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('\\') {
+        out.push_str(&rest[..at]);
+        let escape = &rest[at..];
+        let (c, len) = match escape[1..].chars().next() {
+            Some('\'') => ('\'', 2),
+            Some('"') => ('"', 2),
+            Some('\\') => ('\\', 2),
+            Some('n') => ('\n', 2),
+            Some('t') => ('\t', 2),
+            Some('u') => {
+                let hex = escape[2..]
+                    .strip_prefix('{')
+                    .and_then(|r| r.split_once('}'))
+                    .map(|(hex, _)| hex)
+                    .filter(|hex| (1..=6).contains(&hex.len()));
+                let Some(hex) = hex else {
+                    return Err(&escape[..2]);
+                };
+                let len = hex.len() + 4;
+                match u32::from_str_radix(hex, 16).ok().and_then(char::from_u32) {
+                    Some(c) => (c, len),
+                    None => return Err(&escape[..len]),
+                }
+            }
+            Some(c) => return Err(&escape[..1 + c.len_utf8()]),
+            None => return Err(escape),
+        };
+        out.push(c);
+        rest = &escape[len..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Checks that the source is valid UTF-8, otherwise returns the location of the first invalid byte.
+pub fn validate_utf8<'c>(content: &'c [u8], file: &'static str) -> Result<&'c str, Loc> {
+    std::str::from_utf8(content).map_err(|err| {
+        let valid = &content[..err.valid_up_to()];
+        let bol = valid.iter().rposition(|&c| c == b'\n').map_or(0, |i| i + 1);
+        Loc {
+            file,
+            row: valid.iter().filter(|&&c| c == b'\n').count(),
+            col: valid.len() - bol,
+        }
+    })
+}
+
 fn is_symbol(s: u8) -> bool {
     let lits = LITERALS
         .iter()
         .all(|(lit, _)| lit.as_bytes().iter().all(|&c| c != s));
-    lits && !s.is_ascii_whitespace() && s != b'\''
+    lits && !s.is_ascii_whitespace() && s != b'\'' && s != b'"'
+}
+
+/// Displays a symbol so that it is lexed back as the same single token,
+/// quoting it when it contains whitespace, literals or starts like a command.
+pub struct Quoted<'a>(pub &'a str);
+
+impl<'a> fmt::Display for Quoted<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = self.0;
+        let plain = !s.is_empty()
+            && s.bytes().all(is_symbol)
+            && !s.starts_with(['#', '$'])
+            && !s.starts_with("//");
+        if plain {
+            return write!(f, "{s}");
+        }
+        write!(f, "'")?;
+        for c in s.chars() {
+            // only the escapes `unescape` reads back
+            match c {
+                '\'' | '\\' => write!(f, "\\{c}")?,
+                '\n' => write!(f, "\\n")?,
+                '\t' => write!(f, "\\t")?,
+                c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        write!(f, "'")
+    }
+}
+
+/// Displays a symbol on a tape so that it can be told apart from the cells around it:
+/// spaces are shown as `␣`, other control characters as escapes and the empty symbol as `''`.
+/// A `␣` symbol is shown as `\u{2423}` and a backslash as `\\`,
+/// so they are not mistaken for a space or an escape.
+/// Width and alignment work like they do for `str`.
+pub struct Shown<'a>(pub &'a str);
+
+impl<'a> fmt::Display for Shown<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = self.0;
+        if s.is_empty() {
+            return f.pad("''");
+        }
+        if !s.chars().any(|c| matches!(c, ' ' | '␣' | '\\') || c.is_control()) {
+            return f.pad(s);
+        }
+        let mut shown = String::new();
+        for c in s.chars() {
+            match c {
+                ' ' => shown.push('␣'),
+                '␣' => shown.push_str("\\u{2423}"),
+                '\\' => shown.push_str("\\\\"),
+                c if c.is_control() => shown.extend(c.escape_default()),
+                c => shown.push(c),
+            }
+        }
+        f.pad(&shown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// lexes `src` as one token
+    fn lex_one(src: &str) -> Token<'_> {
+        let mut lexer = Lexer::new(src, "test.tur");
+        let TokenResult::Valid(token) = lexer.next_token() else {
+            panic!("{src} is not a token");
+        };
+        let eof = matches!(lexer.next_token(), TokenResult::Eof { .. });
+        assert!(eof, "{src} is more than one token");
+        token
+    }
+
+    #[test]
+    fn quoted_round_trip() {
+        let syms = [
+            "a", "_", "0", "", " ", "'", "\"", "\\", "#", "#a", "$", "$a", "//", "->", "[", "a b",
+            "\n", "\t", "\r", "\0", "\x1b", "\u{7f}", "\u{85}", "é", "␣",
+        ];
+        for sym in syms {
+            let quoted = Quoted(sym).to_string();
+            let token = lex_one(&quoted);
+            assert_eq!(token.kind, TokenKind::Symbol, "{quoted}");
+            assert_eq!(token.text, sym, "{quoted}");
+        }
+    }
+
+    #[test]
+    fn quoted_plain() {
+        assert_eq!(Quoted("abc").to_string(), "abc");
+        assert_eq!(Quoted("").to_string(), "''");
+        assert_eq!(Quoted("#").to_string(), "'#'");
+        assert_eq!(Quoted("it's").to_string(), "'it\\'s'");
+        assert_eq!(Quoted("\r\n").to_string(), "'\\u{d}\\n'");
+    }
+
+    #[test]
+    fn shown_apart() {
+        let syms = ["a", "", " ", "␣", "\\", "\n", "\\n", "\\u{2423}", "a b", "a␣b"];
+        let shown: Vec<String> = syms.iter().map(|sym| Shown(sym).to_string()).collect();
+        for (i, a) in shown.iter().enumerate() {
+            for b in &shown[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        assert_eq!(shown[2], "␣");
+        assert_eq!(shown[3], "\\u{2423}");
+        assert_eq!(format!("{:>3}", Shown(" ")), "  ␣");
+    }
+
+    #[test]
+    fn unescapes() {
+        assert_eq!(unescape("plain").unwrap(), "plain");
+        assert_eq!(unescape("\\'\\\"\\\\").unwrap(), "'\"\\");
+        assert_eq!(unescape("a\\nb\\tc").unwrap(), "a\nb\tc");
+        assert_eq!(unescape("\\u{d}\\u{1F600}").unwrap(), "\r😀");
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        assert_eq!(unescape("a\\rb"), Err("\\r"));
+        assert_eq!(unescape("\\é"), Err("\\é"));
+        assert_eq!(unescape("\\u{}"), Err("\\u"));
+        assert_eq!(unescape("\\u{1234567}"), Err("\\u"));
+        assert_eq!(unescape("\\u{d800}"), Err("\\u{d800}"));
+        assert_eq!(unescape("\\u{zz}"), Err("\\u{zz}"));
+        assert_eq!(unescape("a\\"), Err("\\"));
+    }
 }
@@ -1,3 +1,16 @@
+pub mod bb;
 pub mod compiler;
+pub mod convert;
+pub mod diagram;
+pub mod fast;
+pub mod graph;
+pub mod interp;
+pub mod json;
+pub mod lexer;
+pub mod opt;
 pub mod parser;
-pub mod lexer;
\ No newline at end of file
+pub mod search;
+pub mod tms;
+pub mod trace;
+pub mod validator;
+pub mod verify;
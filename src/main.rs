@@ -1,110 +1,799 @@
 use std::{
-    io::{stdout, BufWriter, Read, Write},
+    collections::BTreeMap,
+    io::{stdin, stdout, BufWriter, Read, Write},
+    path::Path,
     process::ExitCode,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
 };
 use turir::{
+    bb,
     compiler::Compiler,
-    parser::{self, Dir, ParseErr, Program},
+    convert,
+    diagram::Diagram,
+    graph,
+    interp::{Machine, Stop},
+    fast,
+    lexer::{Quoted, Shown},
+    opt,
+    parser::{self, AnyRun, BbRunCmd, Program, RunCmd},
+    search,
+    tms, trace, validator, verify,
 };
 
-fn read_source(s: &str) -> std::io::Result<Vec<u8>> {
-    let mut f = std::fs::File::open(s)?;
-    let sz = f.metadata()?.len() as usize;
-
-    // hackish way to put a new line at the end of source code
-    let mut buf = Vec::with_capacity(sz + 1);
-    f.read_to_end(&mut buf)?;
-    buf.push(b'\n');
-    Ok(buf)
-}
-
 #[allow(unused_must_use)]
 fn tape_print(tape: &[&str], head: usize, sink: &mut impl Write) {
     write!(sink, "[ ");
     let (last, t) = tape.split_last().expect("tape cannot be empty");
     for t in t {
-        write!(sink, "{t} ");
+        write!(sink, "{} ", Shown(t));
     }
-    write!(sink, "{last} ]");
+    write!(sink, "{} ]", Shown(last));
     write!(sink, "\n  ");
-    for _ in 0..head {
-        write!(sink, "  ");
+    for t in &tape[..head] {
+        write!(sink, "{:w$} ", "", w = Shown(t).to_string().chars().count());
     }
     writeln!(sink, "^");
 }
 
+/// runs the machine to the end without a trace
+fn run_machine<'p, 'c>(
+    program: &'p Program<'c>,
+    run: &RunCmd<'c>,
+    max_steps: Option<usize>,
+    engine: Engine,
+) -> (Machine<'p, 'c>, Stop<'c>) {
+    match engine {
+        Engine::Naive => {
+            let mut machine = Machine::new(program, run);
+            let stop = machine.run(max_steps);
+            (machine, stop)
+        }
+        Engine::Fast => fast::run(program, run, max_steps),
+    }
+}
+
+/// the exit codes of `turir run`, halting in a state that is not a `#reject` state exits with 0
+const EXIT_REJECT: u8 = 1;
+const EXIT_MISSING: u8 = 2;
+const EXIT_STEP_LIMIT: u8 = 3;
+/// usage, IO and parse errors, every command exits with it when it could not do its work
+const EXIT_ERROR: u8 = 4;
+
+/// the exit code of a run that stopped with `stop`, `rejected` when it halted in a `#reject` state
+fn stop_code(stop: Stop, rejected: bool) -> u8 {
+    match stop {
+        Stop::Halted if rejected => EXIT_REJECT,
+        Stop::Halted => 0,
+        Stop::Missing { .. } => EXIT_MISSING,
+        Stop::StepLimit => EXIT_STEP_LIMIT,
+    }
+}
+
+/// folds the exit codes of the runs in order, the first run that does not halt decides the code,
+/// otherwise any rejected run does
+fn fold_code(code: u8, next: u8) -> u8 {
+    match code {
+        0 | EXIT_REJECT => code.max(next),
+        _ => code,
+    }
+}
+
+/// the block a run ends with, the same for both engines: the final tape, then
+/// ` -- HALT -- with <STATE> after <N> steps`, or ` -- STOP -- in <STATE> after <N> steps`
+/// when it did not halt. `#accept` and `#reject` states are told apart
+#[allow(unused_must_use)]
+fn stop_print(program: &Program<'_>, machine: &Machine, stop: Stop, sink: &mut impl Write) {
+    tape_print(&machine.tape, machine.head, sink);
+    let (state, steps) = (machine.state, machine.steps);
+    if stop != Stop::Halted {
+        writeln!(sink, " -- STOP -- in {state} after {steps} steps");
+        return;
+    }
+    let verdict = match state {
+        s if program.is_accept(s) => "accepted ",
+        s if program.is_reject(s) => "rejected ",
+        _ => "",
+    };
+    writeln!(sink, " -- HALT -- {verdict}with {state} after {steps} steps");
+    writeln!(sink);
+}
+
+/// prints the trace of one run and the block it ends with, only that block with the fast engine,
+/// and returns the state it halted in or why it stopped when it did not halt
 #[allow(unused_must_use)]
-fn execute_program<'c, 'k>(program: Program<'_>) -> Result<(), ParseErr<'c, 'k>> {
+fn execute_run<'c>(
+    program: &Program<'c>,
+    run: &RunCmd<'c>,
+    max_steps: Option<usize>,
+    engine: Engine,
+    sink: &mut impl Write,
+) -> Result<&'c str, Stop<'c>> {
+    writeln!(sink, "{run}");
+
+    // the fast engine skips over steps, so only the block the run ends with is printed
+    if engine == Engine::Fast {
+        let (machine, stop) = fast::run(program, run, max_steps);
+        stop_print(program, &machine, stop, sink);
+        return match stop {
+            Stop::Halted => Ok(machine.state),
+            stop => Err(stop),
+        };
+    }
+
+    let mut machine = Machine::new(program, run);
+    let stop = loop {
+        if max_steps.is_some_and(|max| machine.steps >= max) && !machine.is_halted() {
+            break Stop::StepLimit;
+        }
+        let instr = match machine.next_instr() {
+            Ok(instr) => instr,
+            Err(stop) => break stop,
+        };
+        writeln!(
+            sink,
+            "{} {} {} {} {}",
+            instr.state,
+            Shown(instr.read),
+            Shown(instr.write),
+            instr.dir,
+            instr.next_state
+        );
+
+        tape_print(&machine.tape, machine.head, sink);
+
+        machine.apply(instr);
+        sink.flush();
+    };
+    stop_print(program, &machine, stop, sink);
+    match stop {
+        Stop::Halted => Ok(machine.state),
+        stop => Err(stop),
+    }
+}
+
+/// prints the report of a `#run bb` and returns why it stopped
+#[allow(unused_must_use)]
+fn execute_bb_run(run: &BbRunCmd, max_steps: Option<usize>, sink: &mut impl Write) -> Stop<'static> {
+    writeln!(sink, "#run bb \"{}\"", run.notation);
+    let report = run.table.run(max_steps);
+    writeln!(sink, "{report}");
+    writeln!(sink);
+    report.stop
+}
+
+/// Runs every `#run` of the program, `#run bb`s included, in source order.
+/// With more than one job the tape runs are spread over that many threads,
+/// each run's output is buffered and printed in source order once the runs before it are printed.
+/// Exits at the first run that does not halt, the exit code tells why.
+#[allow(unused_must_use)]
+fn execute_program(program: &Program<'_>, max_steps: Option<usize>, engine: Engine, jobs: usize) -> ExitCode {
     let mut sink = BufWriter::new(stdout().lock());
+    let exit = |sink: &mut BufWriter<_>, stop: Stop| -> ! {
+        sink.flush();
+        std::process::exit(stop_code(stop, false).into());
+    };
+    let fail = |sink: &mut BufWriter<_>, stop: Stop| -> ! {
+        sink.flush();
+        eprintln!("{stop}");
+        exit(sink, stop);
+    };
+    // the report of a `#run bb` already tells why it stopped
+    let bb = |sink: &mut BufWriter<_>, run: &BbRunCmd| match execute_bb_run(run, max_steps, sink) {
+        Stop::Halted => {}
+        stop => exit(sink, stop),
+    };
+    let halted = |state: &str| stop_code(Stop::Halted, program.is_reject(state));
+    let mut code = 0;
 
-    for run in program.runs {
-        writeln!(sink, "{run}");
+    if jobs <= 1 {
+        for run in program.all_runs() {
+            let run = match run {
+                AnyRun::Tape(run) => run,
+                AnyRun::Bb(run) => {
+                    bb(&mut sink, run);
+                    continue;
+                }
+            };
+            match execute_run(program, run, max_steps, engine, &mut sink) {
+                Ok(state) => code = fold_code(code, halted(state)),
+                Err(stop) => fail(&mut sink, stop),
+            }
+        }
+    } else {
+        let mut bb_runs = program.bb_runs.iter().peekable();
+        let next = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            for _ in 0..jobs.min(program.runs.len()) {
+                let (tx, next) = (tx.clone(), &next);
+                scope.spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(run) = program.runs.get(i) else {
+                        break;
+                    };
+                    let mut buf = Vec::new();
+                    let result = execute_run(program, run, max_steps, engine, &mut buf);
+                    if tx.send((i, buf, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx);
 
-        let mut tape = run.tape;
-        let mut state = run.state;
-        let mut head = 0;
+            let mut done = BTreeMap::new();
+            let mut printed = 0;
+            for (i, buf, result) in rx {
+                done.insert(i, (buf, result));
+                while let Some((buf, result)) = done.remove(&printed) {
+                    while let Some(run) = bb_runs.next_if(|run| run.at <= printed) {
+                        bb(&mut sink, run);
+                    }
+                    sink.write_all(&buf);
+                    match result {
+                        Ok(state) => code = fold_code(code, halted(state)),
+                        Err(stop) => fail(&mut sink, stop),
+                    }
+                    printed += 1;
+                }
+            }
+        });
+        for run in bb_runs {
+            bb(&mut sink, run);
+        }
+    }
 
-        let jmp_instr = |state, read| match program
-            .program
-            .iter()
-            .find(|instr| instr.state == state && instr.read == read)
-        {
-            Some(s) => s,
-            None => {
-                eprintln!("State '{state}' and read '{read}' combination is not defined");
-                std::process::exit(0);
+    sink.flush();
+    ExitCode::from(code)
+}
+
+/// prints the trace of every run as JSON records, either as one array or one record per line
+fn trace_program(program: &Program<'_>, lines: bool, max_steps: Option<usize>) -> ExitCode {
+    let mut sink = BufWriter::new(stdout().lock());
+    let mut first = true;
+    let mut emit = |record: &trace::Record| -> std::io::Result<()> {
+        match (lines, first) {
+            (true, _) => writeln!(sink, "{record}")?,
+            (false, true) => write!(sink, "[\n{record}")?,
+            (false, false) => write!(sink, ",\n{record}")?,
+        }
+        first = false;
+        Ok(())
+    };
+
+    let mut code = 0;
+    let mut written = Ok(());
+    for (i, run) in program.all_runs().into_iter().enumerate() {
+        // a `#run bb` is traced as the run of the program of its table
+        let table;
+        let (program, run) = match run {
+            AnyRun::Tape(run) => (program, run),
+            AnyRun::Bb(run) => {
+                table = run.table.program();
+                (&table, &table.runs[0])
             }
         };
+        written = trace::trace_run(program, run, i, max_steps, &mut emit)
+            .map(|(state, stop)| code = fold_code(code, stop_code(stop, program.is_reject(state))));
+        if written.is_err() {
+            break;
+        }
+    }
+    let written = written
+        .and_then(|_| match (lines, first) {
+            (true, _) => Ok(()),
+            (false, true) => writeln!(sink, "[]"),
+            (false, false) => writeln!(sink, "\n]"),
+        })
+        .and_then(|_| sink.flush());
 
-        // tape has got at least one element
-        let mut instr = jmp_instr(state, tape[0]);
-        let default_sym = tape.last().unwrap().to_string();
-        loop {
-            writeln!(sink, "{}", instr);
+    match written {
+        Ok(()) => ExitCode::from(code),
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
 
-            tape_print(&tape, head, &mut sink);
+/// writes a space-time diagram for every run instead of printing the trace
+fn draw_program(program: &Program<'_>, path: &str, max_steps: Option<usize>) -> ExitCode {
+    let path = Path::new(path);
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    if !matches!(ext, "svg" | "ppm") {
+        eprintln!("{}: diagrams can only be written as .svg or .ppm", path.display());
+        return ExitCode::from(EXIT_ERROR);
+    }
 
-            tape[head] = instr.write;
-            state = instr.next_state;
+    let runs = program.all_runs();
+    let mut code = 0;
+    for (i, run) in runs.iter().enumerate() {
+        let path = match runs.len() {
+            1 => path.to_path_buf(),
+            _ => {
+                let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+                path.with_file_name(format!("{stem}-{}.{ext}", i + 1))
+            }
+        };
+        let table;
+        let (program, cmd) = match run {
+            AnyRun::Tape(run) => (program, *run),
+            AnyRun::Bb(run) => {
+                table = run.table.program();
+                (&table, &table.runs[0])
+            }
+        };
+        let (diagram, state, stop) = Diagram::record(program, cmd, max_steps);
+        code = fold_code(code, stop_code(stop, program.is_reject(state)));
 
-            match instr.dir {
-                Dir::Left => head -= 1,
-                Dir::Right => head += 1,
+        let written = std::fs::File::create(&path).and_then(|f| {
+            let mut sink = BufWriter::new(f);
+            match ext {
+                "svg" => diagram.write_svg(&mut sink)?,
+                _ => diagram.write_ppm(&mut sink)?,
             }
-            if program.halt_syms.iter().any(|&c| instr.next_state == c) {
-                break;
+            sink.flush()
+        });
+        if let Err(err) = written {
+            eprintln!("{}: {err}", path.display());
+            return ExitCode::from(EXIT_ERROR);
+        }
+        match run {
+            AnyRun::Tape(run) => println!("{run}"),
+            AnyRun::Bb(run) => println!("#run bb \"{}\"", run.notation),
+        }
+        println!(" -- {stop} -- diagram is written to {}", path.display());
+    }
+
+    ExitCode::from(code)
+}
+
+/// prints both tapes with their cells aligned and marks the cells that differ
+#[allow(unused_must_use)]
+fn tape_diff(expected: &[&str], actual: &[&str], sink: &mut impl Write) {
+    let len = expected.len().max(actual.len());
+    fn cell<'a>(tape: &[&'a str], i: usize) -> &'a str {
+        tape.get(i).copied().unwrap_or("")
+    }
+    // cells past the end of a tape are left empty
+    let shown = |tape: &[&str], i: usize| tape.get(i).map_or(String::new(), |s| Shown(s).to_string());
+    let width = |i| shown(expected, i).chars().count().max(shown(actual, i).chars().count());
+
+    for (name, tape) in [("expected", expected), ("actual", actual)] {
+        write!(sink, "    {name:<8} [ ");
+        for i in 0..len {
+            write!(sink, "{:<w$} ", shown(tape, i), w = width(i));
+        }
+        writeln!(sink, "]");
+    }
+    write!(sink, "               ");
+    for i in 0..len {
+        let mark = if cell(expected, i) == cell(actual, i) { " " } else { "^" };
+        write!(sink, "{mark:<w$} ", w = width(i));
+    }
+    writeln!(sink);
+}
+
+/// runs every `#run` of the program and compares the result with its `#expect`
+/// returns the number of passed and failed runs
+#[allow(unused_must_use)]
+fn test_program(
+    program: &Program<'_>,
+    max_steps: usize,
+    engine: Engine,
+    sink: &mut impl Write,
+) -> (usize, usize) {
+    let (mut passed, mut failed) = (0, 0);
+
+    for run in program.runs.iter() {
+        write!(sink, "{run} ... ");
+
+        let (machine, stop) = run_machine(program, run, Some(max_steps), engine);
+
+        // halting in a `#reject` state only passes when the `#expect` line asks for it
+        let ok = stop == Stop::Halted
+            && match &run.expect {
+                Some(expect) => {
+                    expect.tape == machine.tape
+                        && expect.state == machine.state
+                        && expect.head.is_none_or(|h| h == machine.head)
+                        && expect.steps.is_none_or(|s| s == machine.steps)
+                }
+                None => !program.is_reject(machine.state),
+            };
+        if ok {
+            passed += 1;
+            writeln!(sink, "ok");
+            continue;
+        }
+
+        failed += 1;
+        writeln!(sink, "FAILED");
+        if stop != Stop::Halted {
+            writeln!(sink, "    {stop} after {} steps", machine.steps);
+        } else if run.expect.is_none() {
+            writeln!(sink, "    rejected with {} after {} steps", machine.state, machine.steps);
+        }
+        if let Some(expect) = &run.expect {
+            tape_diff(&expect.tape, &machine.tape, sink);
+            writeln!(
+                sink,
+                "    expected state {}, head {}, steps {}",
+                expect.state,
+                expect.head.map_or("any".to_string(), |h| h.to_string()),
+                expect.steps.map_or("any".to_string(), |s| s.to_string()),
+            );
+            writeln!(
+                sink,
+                "    actual   state {}, head {}, steps {}",
+                machine.state, machine.head, machine.steps
+            );
+        }
+    }
+
+    (passed, failed)
+}
+
+#[allow(unused_must_use)]
+fn verify_program(program: &Program<'_>, args: &Args) -> ExitCode {
+    let Some(spec) = &program.spec else {
+        eprintln!("{}: no `#spec` to verify", args.files[0]);
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let max_steps = args.max_steps.unwrap_or(DEFAULT_VERIFY_MAX_STEPS);
+
+    let reference = match (&args.against, &args.expected) {
+        (Some(file), None) => {
+            let file: &'static str = Box::leak(file.clone().into_boxed_str());
+            let Some(mut program) = load_program(file, Lang::of(file)) else {
+                return ExitCode::from(EXIT_ERROR);
+            };
+            let state = program.spec.as_ref().map_or(spec.state, |s| s.state);
+            // the reference runs on the inputs of the verified machine
+            program.expand(&[&spec.alphabet[..], &[spec.blank]].concat());
+            Reference::Machine(Box::new(program), state)
+        }
+        (None, Some(file)) => {
+            let content = match std::fs::read_to_string(file) {
+                Ok(c) => Box::leak(c.into_boxed_str()),
+                Err(err) => {
+                    eprintln!("{file}: {err}");
+                    return ExitCode::from(EXIT_ERROR);
+                }
+            };
+            match verify::parse_expected(content) {
+                Ok(pairs) => Reference::Expected(pairs),
+                Err(line) => {
+                    eprintln!("{file}:{line}: expected `<INPUT> => <OUTPUT>`");
+                    return ExitCode::from(EXIT_ERROR);
+                }
             }
-            if tape.len() <= head {
-                tape.push(&default_sym)
+        }
+        _ => {
+            eprintln!("verify needs exactly one of --against <machine>.tur or --expected <file>");
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    let inputs = match verify::inputs(&spec.alphabet, spec.max_len) {
+        Ok(inputs) => inputs,
+        Err(err) => {
+            eprintln!("{}: {err}", args.files[0]);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let mut sink = BufWriter::new(stdout().lock());
+    let (mut total, mut failed) = (0, 0);
+    let mut counterexample = None;
+    for input in inputs {
+        total += 1;
+        let outcome = verify::run_input(program, spec.state, &input, spec.blank, max_steps);
+        let expected = match &reference {
+            Reference::Machine(reference, state) => {
+                let expected = verify::run_input(reference, state, &input, spec.blank, max_steps);
+                if outcome.same_as(&expected, spec.blank) {
+                    continue;
+                }
+                expected.to_string()
             }
+            Reference::Expected(pairs) => match pairs.iter().find(|e| e.input == input) {
+                Some(e) => {
+                    if outcome.stop == Stop::Halted
+                        && outcome.trimmed(spec.blank) == verify::trim_blanks(&e.output, spec.blank)
+                    {
+                        continue;
+                    }
+                    cells(&e.output)
+                }
+                None => "no expected output for this input".to_string(),
+            },
+        };
+        failed += 1;
+        if counterexample.is_none() {
+            counterexample = Some((input, outcome, expected));
+        }
+    }
+
+    let alphabet = spec.alphabet.join(" ");
+    let max_len = spec.max_len;
+    match counterexample {
+        None => {
+            writeln!(
+                sink,
+                "verified {total} inputs up to length {max_len} over [ {alphabet} ]: ok"
+            );
             sink.flush();
+            ExitCode::SUCCESS
+        }
+        Some((input, outcome, expected)) => {
+            writeln!(
+                sink,
+                "{failed} of {total} inputs up to length {max_len} over [ {alphabet} ] differ"
+            );
+            writeln!(sink, "smallest counterexample:");
+            writeln!(sink, "    input    {}", cells(&input));
+            writeln!(sink, "    actual   {outcome}");
+            writeln!(sink, "    expected {expected}");
+            sink.flush();
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn cells(tape: &[&str]) -> String {
+    let mut s = "[ ".to_string();
+    for t in tape {
+        s += &format!("{} ", Shown(t));
+    }
+    s.push(']');
+    s
+}
+
+/// the state a machine is started from when it is compared with another one
+fn entry_state<'c>(program: &Program<'c>) -> Option<&'c str> {
+    match (&program.spec, program.runs.first()) {
+        (Some(spec), _) => Some(spec.state),
+        (None, Some(run)) => Some(run.state),
+        (None, None) => None,
+    }
+}
 
-            instr = jmp_instr(state, tape[head])
+#[allow(unused_must_use)]
+fn equiv_programs(args: &Args) -> ExitCode {
+    let [a, b] = &args.files[..] else {
+        eprintln!("equiv expects exactly two source files");
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let mut programs = Vec::new();
+    for file in [a, b] {
+        let file: &'static str = Box::leak(file.clone().into_boxed_str());
+        let Some(program) = load_program(file, Lang::of(file)) else {
+            return ExitCode::from(EXIT_ERROR);
+        };
+        let Some(state) = entry_state(&program) else {
+            eprintln!("{file}: no `#spec` or `#run` to take the initial state from");
+            return ExitCode::from(EXIT_ERROR);
+        };
+        programs.push((program, state));
+    }
+
+    let alphabet: Vec<&str> = match (&args.alphabet, &programs[0].0.spec) {
+        (Some(alphabet), _) => {
+            let alphabet: &'static str = Box::leak(alphabet.clone().into_boxed_str());
+            alphabet.split(',').filter(|s| !s.is_empty()).collect()
+        }
+        (None, Some(spec)) => spec.alphabet.clone(),
+        (None, None) => {
+            eprintln!("no --alphabet is given and {} has no `#spec`", args.files[0]);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    // the inputs can have symbols that do not appear in the machines
+    for (program, _) in programs.iter_mut() {
+        program.expand(&alphabet);
+    }
+    let [(a, a_state), (b, b_state)] = &programs[..] else {
+        unreachable!()
+    };
+    let Some(max_len) = args.max_len.or(a.spec.as_ref().map(|s| s.max_len)) else {
+        eprintln!("no --max-len is given and {} has no `#spec`", args.files[0]);
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let Some(&blank) = alphabet.first() else {
+        eprintln!("alphabet cannot be empty");
+        return ExitCode::from(EXIT_ERROR);
+    };
+    let max_steps = args.max_steps.unwrap_or(DEFAULT_VERIFY_MAX_STEPS);
+
+    let mut sink = BufWriter::new(stdout().lock());
+    let diff = verify::first_difference(
+        verify::Entry {
+            program: a,
+            state: a_state,
+        },
+        verify::Entry {
+            program: b,
+            state: b_state,
+        },
+        &alphabet,
+        max_len,
+        blank,
+        max_steps,
+    );
+    let diff = match diff {
+        Ok(diff) => diff,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    let alphabet = alphabet.join(" ");
+    match diff {
+        None => {
+            writeln!(
+                sink,
+                "equivalent on every input up to length {max_len} over [ {alphabet} ]"
+            );
+            sink.flush();
+            ExitCode::SUCCESS
+        }
+        Some((input, out_a, out_b)) => {
+            writeln!(sink, "machines differ on input {}", cells(&input));
+            writeln!(sink, "    {}: {out_a}", args.files[0]);
+            writeln!(sink, "    {}: {out_b}", args.files[1]);
+            sink.flush();
+            ExitCode::FAILURE
         }
-        tape_print(&tape, head, &mut sink);
-        writeln!(sink, " -- HALT -- with {}", state);
-        writeln!(sink);
     }
+}
 
+#[allow(unused_must_use)]
+fn search_machines(args: &Args) -> ExitCode {
+    let (Some(states), Some(symbols)) = (args.states, args.symbols) else {
+        eprintln!("bb-search expects --states and --symbols");
+        return ExitCode::from(EXIT_ERROR);
+    };
+    if states == 0 || states >= bb::STATES.len() || symbols < 2 || symbols > bb::SYMBOLS.len() {
+        eprintln!(
+            "bb-search supports 1 to {} states and 2 to {} symbols",
+            bb::STATES.len() - 1,
+            bb::SYMBOLS.len()
+        );
+        return ExitCode::from(EXIT_ERROR);
+    }
+    let max_steps = args.max_steps.unwrap_or(DEFAULT_SEARCH_MAX_STEPS);
+    let summary = search::search(states, symbols, max_steps, args.top.unwrap_or(DEFAULT_SEARCH_TOP));
+
+    let mut sink = BufWriter::new(stdout().lock());
+    writeln!(
+        sink,
+        "{} machines: {} halting, {} cyclers, {} translated cyclers, {} undecided after {max_steps} steps",
+        summary.machines, summary.halting, summary.cyclers, summary.translated, summary.undecided
+    );
+    for (title, list) in [("steps", &summary.by_steps), ("ones", &summary.by_ones)] {
+        writeln!(sink, "\ntop by {title}:");
+        for found in list.iter() {
+            writeln!(sink, "{}  {} steps, {} ones", found.table, found.steps, found.ones);
+        }
+    }
     sink.flush();
+    ExitCode::SUCCESS
+}
 
-    Ok(())
+enum Reference<'c> {
+    Machine(Box<Program<'c>>, &'c str),
+    Expected(Vec<verify::Expected<'c>>),
 }
 
 enum CmdArg {
     Run,
     Compile,
+    Check,
+    Test,
+    Verify,
+    Equiv,
+    Opt,
+    Graph,
+    Convert,
+    Import,
+    Export,
+    BbSearch,
+}
+
+struct Args {
+    cmd: CmdArg,
+    files: Vec<String>,
+    max_steps: Option<usize>,
+    against: Option<String>,
+    expected: Option<String>,
+    alphabet: Option<String>,
+    max_len: Option<usize>,
+    diagram: Option<String>,
+    format: Format,
+    from: Option<Lang>,
+    to: Option<Lang>,
+    bb: Option<String>,
+    states: Option<usize>,
+    symbols: Option<usize>,
+    top: Option<usize>,
+    engine: Engine,
+    jobs: Option<usize>,
+    input: Option<String>,
+    state: Option<String>,
+    completeness: bool,
+    stub: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Engine {
+    Naive,
+    Fast,
 }
 
-const USAGE: &str = "\trun <source code>.tur\n\tcompile <source code>.tur";
+enum Format {
+    Text,
+    Json,
+    JsonLines,
+}
+
+/// the syntax a program is read from or written in
+#[derive(Clone, Copy, PartialEq)]
+enum Lang {
+    Tur,
+    Json,
+    Yaml,
+    Tms,
+    Bb,
+}
+
+impl Lang {
+    /// `.json` files are JSON programs, anything else is `.tur` source
+    fn of(file: &str) -> Lang {
+        match Path::new(file).extension().and_then(|e| e.to_str()) {
+            Some("json") => Lang::Json,
+            _ => Lang::Tur,
+        }
+    }
+}
 
-fn parse_args() -> Option<(CmdArg, String)> {
+const USAGE: &str = "\trun <source code>.tur [--max-steps <N>] [--diagram <out>.svg|.ppm] [--format text|json|jsonl] [--engine naive|fast] [--jobs <N>] [--input <tapes file>|-] [--state <STATE>]
+\trun --bb <NOTATION> [--max-steps <N>]
+\tcompile <source code>.tur
+\tcheck <source code>.tur [--completeness] [--stub <STATE>]
+\ttest <source code>.tur... [--max-steps <N>] [--engine naive|fast]
+\tverify <source code>.tur (--against <reference>.tur | --expected <file>) [--max-steps <N>]
+\topt <source code>.tur
+\tgraph <source code>.tur
+\tconvert <source code>.tur|.json [--from tur|json|tms|bb] [--to tur|json|yaml|tms|bb]
+\timport <file> --from tms|bb
+\texport <source code>.tur --to tms|bb
+\tbb-search --states <N> --symbols <K> [--max-steps <N>] [--top <N>]
+\tequiv <a>.tur <b>.tur [--alphabet <SYM>,<SYM>...] [--max-len <N>] [--max-steps <N>]";
+
+const DEFAULT_TEST_MAX_STEPS: usize = 1_000_000;
+const DEFAULT_VERIFY_MAX_STEPS: usize = 10_000;
+const DEFAULT_SEARCH_MAX_STEPS: usize = 10_000;
+const DEFAULT_SEARCH_TOP: usize = 5;
+
+fn parse_args() -> Option<Args> {
     let mut args = std::env::args();
     let r = args.next()?;
 
     let cmd = match args.next().as_deref() {
         Some("compile") => CmdArg::Compile,
         Some("run") => CmdArg::Run,
+        Some("check") => CmdArg::Check,
+        Some("test") => CmdArg::Test,
+        Some("verify") => CmdArg::Verify,
+        Some("equiv") => CmdArg::Equiv,
+        Some("opt") => CmdArg::Opt,
+        Some("graph") => CmdArg::Graph,
+        Some("convert") => CmdArg::Convert,
+        Some("import") => CmdArg::Import,
+        Some("export") => CmdArg::Export,
+        Some("bb-search") => CmdArg::BbSearch,
         Some(c) => {
             eprintln!("{c} is not a valid command\nUsage: {r}\n{USAGE}");
             return None;
@@ -114,43 +803,441 @@ fn parse_args() -> Option<(CmdArg, String)> {
             return None;
         }
     };
-    let f = match args.next() {
-        Some(f) => f,
-        None => {
-            eprintln!("No source file is provided\nUsage: {r}\n{USAGE}");
-            return None;
+
+    let mut files = Vec::new();
+    let mut max_steps = None;
+    let (mut against, mut expected) = (None, None);
+    let (mut alphabet, mut max_len) = (None, None);
+    let mut diagram = None;
+    let mut format = Format::Text;
+    let (mut from, mut to) = (None, None);
+    let mut bb = None;
+    let (mut states, mut symbols, mut top) = (None, None, None);
+    let mut engine = Engine::Naive;
+    let mut jobs = None;
+    let (mut input, mut state) = (None, None);
+    let (mut completeness, mut stub) = (false, None);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--alphabet" => match args.next() {
+                Some(a) => alphabet = Some(a),
+                None => {
+                    eprintln!("--alphabet expects comma separated symbols\nUsage: {r}\n{USAGE}");
+                    return None;
+                }
+            },
+            "--format" => match args.next().as_deref() {
+                Some("text") => format = Format::Text,
+                Some("json") => format = Format::Json,
+                Some("jsonl") => format = Format::JsonLines,
+                _ => {
+                    eprintln!("--format expects text, json or jsonl\nUsage: {r}\n{USAGE}");
+                    return None;
+                }
+            },
+            "--bb" => match args.next() {
+                Some(notation) => bb = Some(notation),
+                None => {
+                    eprintln!("--bb expects a machine like 1RB1LB_1LA1RZ\nUsage: {r}\n{USAGE}");
+                    return None;
+                }
+            },
+            "--engine" => match args.next().as_deref() {
+                Some("naive") => engine = Engine::Naive,
+                Some("fast") => engine = Engine::Fast,
+                _ => {
+                    eprintln!("--engine expects naive or fast\nUsage: {r}\n{USAGE}");
+                    return None;
+                }
+            },
+            "--from" => match args.next().as_deref() {
+                Some("tur") => from = Some(Lang::Tur),
+                Some("json") => from = Some(Lang::Json),
+                Some("tms") => from = Some(Lang::Tms),
+                Some("bb") => from = Some(Lang::Bb),
+                _ => {
+                    eprintln!("--from expects tur, json, tms or bb\nUsage: {r}\n{USAGE}");
+                    return None;
+                }
+            },
+            "--to" => match args.next().as_deref() {
+                Some("tur") => to = Some(Lang::Tur),
+                Some("json") => to = Some(Lang::Json),
+                Some("yaml") => to = Some(Lang::Yaml),
+                Some("tms") => to = Some(Lang::Tms),
+                Some("bb") => to = Some(Lang::Bb),
+                _ => {
+                    eprintln!("--to expects tur, json, yaml, tms or bb\nUsage: {r}\n{USAGE}");
+                    return None;
+                }
+            },
+            "--max-len" | "--states" | "--symbols" | "--top" | "--jobs" => {
+                let Some(n) = args.next().and_then(|n| n.parse().ok()) else {
+                    eprintln!("{arg} expects a number\nUsage: {r}\n{USAGE}");
+                    return None;
+                };
+                match arg.as_str() {
+                    "--max-len" => max_len = Some(n),
+                    "--states" => states = Some(n),
+                    "--symbols" => symbols = Some(n),
+                    "--top" => top = Some(n),
+                    _ => jobs = Some(n),
+                }
+            }
+            "--state" => match args.next() {
+                Some(s) => state = Some(s),
+                None => {
+                    eprintln!("--state expects the state runs start from\nUsage: {r}\n{USAGE}");
+                    return None;
+                }
+            },
+            "--completeness" => completeness = true,
+            "--stub" => match args.next() {
+                Some(s) => stub = Some(s),
+                None => {
+                    eprintln!("--stub expects the state missing transitions go to\nUsage: {r}\n{USAGE}");
+                    return None;
+                }
+            },
+            "--against" | "--expected" | "--diagram" | "--input" => {
+                let Some(f) = args.next() else {
+                    eprintln!("{arg} expects a file\nUsage: {r}\n{USAGE}");
+                    return None;
+                };
+                match arg.as_str() {
+                    "--against" => against = Some(f),
+                    "--expected" => expected = Some(f),
+                    "--input" => input = Some(f),
+                    _ => diagram = Some(f),
+                }
+            }
+            "--max-steps" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => max_steps = Some(n),
+                None => {
+                    eprintln!("--max-steps expects a number\nUsage: {r}\n{USAGE}");
+                    return None;
+                }
+            },
+            o if o.starts_with("--") => {
+                eprintln!("{o} is not a valid option\nUsage: {r}\n{USAGE}");
+                return None;
+            }
+            _ => files.push(arg),
         }
-    };
+    }
+    if bb.is_some() && (!matches!(cmd, CmdArg::Run) || !files.is_empty()) {
+        eprintln!("--bb runs a machine without a source file\nUsage: {r}\n{USAGE}");
+        return None;
+    }
+    if (input.is_some() || state.is_some()) && (!matches!(cmd, CmdArg::Run) || bb.is_some()) {
+        eprintln!("--input and --state give the tapes of a run\nUsage: {r}\n{USAGE}");
+        return None;
+    }
+    if (completeness || stub.is_some()) && !matches!(cmd, CmdArg::Check) {
+        eprintln!("--completeness and --stub are options of check\nUsage: {r}\n{USAGE}");
+        return None;
+    }
+    if engine == Engine::Fast && (diagram.is_some() || !matches!(format, Format::Text)) {
+        eprintln!(
+            "--engine fast skips steps, it cannot be combined with --diagram or --format json|jsonl\n\
+             Usage: {r}\n{USAGE}"
+        );
+        return None;
+    }
+    if files.is_empty() && bb.is_none() && !matches!(cmd, CmdArg::BbSearch) {
+        eprintln!("No source file is provided\nUsage: {r}\n{USAGE}");
+        return None;
+    }
 
-    Some((cmd, f))
+    Some(Args {
+        cmd,
+        files,
+        max_steps,
+        against,
+        expected,
+        alphabet,
+        max_len,
+        diagram,
+        format,
+        from,
+        to,
+        bb,
+        states,
+        symbols,
+        top,
+        engine,
+        jobs,
+        input,
+        state,
+        completeness,
+        stub,
+    })
 }
 
-fn main() -> ExitCode {
-    let Some((cmd, file)) = parse_args() else {
-        return ExitCode::FAILURE;
+#[allow(unused_must_use)]
+fn run_tests(files: Vec<String>, max_steps: usize, engine: Engine) -> ExitCode {
+    let mut sink = BufWriter::new(stdout().lock());
+    let (mut passed, mut failed) = (0, 0);
+    // a file that cannot be read or run makes the whole test an error rather than a failure
+    let mut error = false;
+
+    for file in files {
+        let file: &'static str = Box::leak(file.into_boxed_str());
+        let program = match read_program(file, Lang::of(file)) {
+            Ok(p) => p,
+            Err(err) => {
+                writeln!(sink, "{err}");
+                failed += 1;
+                error = true;
+                continue;
+            }
+        };
+
+        let diags = validator::validate_symbols(&program);
+        if !diags.is_empty() {
+            for diag in diags.iter() {
+                writeln!(sink, "{file}: {diag}");
+            }
+            failed += 1;
+            error = true;
+            continue;
+        }
+
+        writeln!(sink, "{file}");
+        let (p, f) = test_program(&program, max_steps, engine, &mut sink);
+        passed += p;
+        failed += f;
+        writeln!(sink);
+    }
+
+    writeln!(sink, "test result: {passed} passed; {failed} failed");
+    sink.flush();
+
+    match (error, failed) {
+        (true, _) => ExitCode::from(EXIT_ERROR),
+        (false, 0) => ExitCode::SUCCESS,
+        (false, _) => ExitCode::FAILURE,
+    }
+}
+
+/// Replaces the runs of the program with a run per tape of `--input`, or of stdin when it is `-` or only
+/// `--state` is given. Runs start from `--state`, or from the state of the `#spec` or first `#run`.
+fn read_input_runs(program: &mut Program<'static>, args: &Args) -> bool {
+    let state = match &args.state {
+        Some(state) => Some(&*Box::leak(state.clone().into_boxed_str())),
+        None => entry_state(program),
+    };
+    let Some(state) = state else {
+        eprintln!("--input expects a --state, the program has no `#spec` or `#run` to start from");
+        return false;
     };
-    let file: &'static str = Box::leak(file.into_boxed_str());
-    let content = read_source(file).unwrap();
 
-    let program = match parser::parse_source(&content, file) {
-        Ok(p) => p,
+    let (content, file) = match args.input.as_deref() {
+        Some(path) if path != "-" => (parser::read_source(path), path),
+        _ => {
+            let mut buf = Vec::new();
+            let read = stdin().read_to_end(&mut buf).map(|_| {
+                buf.push(b'\n');
+                buf
+            });
+            (read, "<stdin>")
+        }
+    };
+    let content = match content {
+        Ok(c) => Box::leak(c.into_boxed_slice()),
+        Err(err) => {
+            eprintln!("{file}: {err}");
+            return false;
+        }
+    };
+    let file: &'static str = Box::leak(file.to_string().into_boxed_str());
+    match parser::parse_tapes(content, file) {
+        Ok(tapes) => {
+            program.runs = tapes
+                .into_iter()
+                .map(|(tape, head)| RunCmd {
+                    tape,
+                    head,
+                    state,
+                    expect: None,
+                })
+                .collect();
+            program.bb_runs.clear();
+            // `*` rules have to match the symbols of the new tapes as well
+            program.expand(&[]);
+            true
+        }
         Err(err) => {
             eprintln!("{err}");
-            return ExitCode::FAILURE;
+            false
+        }
+    }
+}
+
+/// reads a program in the given syntax, errors are ready to be printed
+fn read_program(file: &'static str, lang: Lang) -> Result<Program<'static>, String> {
+    if lang != Lang::Tur {
+        let content = std::fs::read_to_string(file).map_err(|err| format!("{file}: {err}"))?;
+        let content = Box::leak(content.into_boxed_str());
+        let program = match lang {
+            Lang::Json => convert::from_json(content).map_err(|err| err.to_string()),
+            Lang::Tms => tms::parse(content).map_err(|err| err.to_string()),
+            Lang::Bb => bb::parse(content).map_err(|err| err.to_string()),
+            Lang::Tur | Lang::Yaml => unreachable!("not an input format"),
+        };
+        return program.map_err(|err| format!("{file}: {err}"));
+    }
+    let content = parser::read_source(file).map_err(|err| format!("{file}: {err}"))?;
+    parser::parse_source(Box::leak(content.into_boxed_slice()), file).map_err(|err| err.to_string())
+}
+
+fn load_program(file: &'static str, lang: Lang) -> Option<Program<'static>> {
+    read_program(file, lang).map_err(|err| eprintln!("{err}")).ok()
+}
+
+fn main() -> ExitCode {
+    let Some(args) = parse_args() else {
+        return ExitCode::from(EXIT_ERROR);
+    };
+    match args.cmd {
+        CmdArg::Test => {
+            return run_tests(
+                args.files,
+                args.max_steps.unwrap_or(DEFAULT_TEST_MAX_STEPS),
+                args.engine,
+            )
+        }
+        CmdArg::Equiv => return equiv_programs(&args),
+        CmdArg::BbSearch => return search_machines(&args),
+        CmdArg::Run if args.files.is_empty() => {
+            let notation = args.bb.as_deref().unwrap_or_default();
+            return match bb::Table::parse(notation) {
+                Ok(table) => {
+                    let report = table.run(args.max_steps);
+                    println!("{report}");
+                    ExitCode::from(stop_code(report.stop, false))
+                }
+                Err(err) => {
+                    eprintln!("{notation}: {err}");
+                    ExitCode::from(EXIT_ERROR)
+                }
+            };
         }
+        _ => {}
+    }
+
+    match (&args.cmd, &args.from, &args.to) {
+        (CmdArg::Import, None, _) => {
+            eprintln!("import expects --from tms or bb");
+            return ExitCode::from(EXIT_ERROR);
+        }
+        (CmdArg::Export, _, None) => {
+            eprintln!("export expects --to tms or bb");
+            return ExitCode::from(EXIT_ERROR);
+        }
+        _ => {}
+    }
+
+    let file: &'static str = Box::leak(args.files[0].clone().into_boxed_str());
+    let from = args.from.unwrap_or(Lang::of(file));
+    let Some(mut program) = load_program(file, from) else {
+        return ExitCode::from(EXIT_ERROR);
     };
+    if (args.input.is_some() || args.state.is_some()) && !read_input_runs(&mut program, &args) {
+        return ExitCode::from(EXIT_ERROR);
+    }
+    // the tapes of `--input` are checked against the alphabets as well
+    if matches!(args.cmd, CmdArg::Run) {
+        let diags = validator::validate_symbols(&program);
+        for diag in diags.iter() {
+            eprintln!("{diag}");
+        }
+        if !diags.is_empty() {
+            return ExitCode::from(EXIT_ERROR);
+        }
+    }
 
-    match cmd {
-        CmdArg::Run => {
-            if let Err(err) = execute_program(program) {
-                eprintln!("{err}");
-                return ExitCode::FAILURE;
+    match args.cmd {
+        CmdArg::Run => match (&args.diagram, &args.format) {
+            (Some(path), _) => return draw_program(&program, path, args.max_steps),
+            (None, Format::Text) => {
+                return execute_program(&program, args.max_steps, args.engine, args.jobs.unwrap_or(1))
+            }
+            (None, Format::Json) => return trace_program(&program, false, args.max_steps),
+            (None, Format::JsonLines) => return trace_program(&program, true, args.max_steps),
+        },
+        CmdArg::Verify => return verify_program(&program, &args),
+        CmdArg::Opt => {
+            let count = |p: &Program| {
+                let mut states: Vec<&str> = p.program.iter().map(|i| i.state).collect();
+                states.sort();
+                states.dedup();
+                (states.len(), p.program.len())
+            };
+            let before = count(&program);
+            let program = opt::minimize(program);
+            let after = count(&program);
+            eprintln!(
+                "{} states with {} rules -> {} states with {} rules",
+                before.0, before.1, after.0, after.1
+            );
+            print!("{program}");
+        }
+        CmdArg::Graph => print!("{}", graph::Dot(&program)),
+        CmdArg::Convert | CmdArg::Import | CmdArg::Export => {
+            let to = args.to.unwrap_or(match from {
+                Lang::Tur => Lang::Json,
+                _ => Lang::Tur,
+            });
+            let start = entry_state(&program)
+                .or(program.program.first().map(|i| i.state))
+                .unwrap_or(bb::START);
+            let out = match to {
+                Lang::Tur => Ok(program.to_string()),
+                Lang::Json => Ok(convert::Json(&program).to_string()),
+                Lang::Yaml => Ok(convert::Yaml(&program).to_string()),
+                Lang::Tms => {
+                    let name = Path::new(file).file_stem().map_or(file.into(), |s| s.to_string_lossy());
+                    tms::export(&program, &name, start).map_err(|err| err.to_string())
+                }
+                Lang::Bb => bb::export(&program, start)
+                    .map(|bb| bb + "\n")
+                    .map_err(|err| err.to_string()),
+            };
+            match out {
+                Ok(out) => print!("{out}"),
+                Err(err) => {
+                    eprintln!("{file}: {err}");
+                    return ExitCode::from(EXIT_ERROR);
+                }
             }
         }
         CmdArg::Compile => {
             let mut compiler = Compiler::default();
             compiler.compile_program(program);
         }
+        CmdArg::Check => {
+            let mut diags = validator::validate(&program);
+            // an `#alphabet` already reports missing transitions for all of its symbols
+            if (args.completeness || args.stub.is_some()) && program.alphabet.is_none() {
+                diags.extend(validator::completeness(&program));
+            }
+            for diag in diags.iter() {
+                eprintln!("{diag}");
+            }
+            if let Some(reject) = &args.stub {
+                for diag in diags.iter() {
+                    if let validator::Diagnostic::MissingTransition { state, read } = diag {
+                        let (state, read, reject) = (Quoted(state), Quoted(read), Quoted(reject));
+                        println!("{state} {read} {read} - {reject}");
+                    }
+                }
+            }
+            if diags.iter().any(|d| d.is_error()) {
+                return ExitCode::FAILURE;
+            }
+        }
+        CmdArg::Test | CmdArg::Equiv | CmdArg::BbSearch => unreachable!(),
     }
 
     ExitCode::SUCCESS
@@ -0,0 +1,242 @@
+use crate::parser::{Dir, Instr, Pat, Program};
+use std::collections::{HashMap, HashSet};
+
+/// what a state does on a symbol: write, move and the index of the next state or block,
+/// `None` if it has no rule for it
+type Action<'c> = Option<(&'c str, Dir, usize)>;
+
+/// states that can be reached from the initial states of `#run`s and `#spec`,
+/// every state is a root if there are none
+fn reachable<'c>(program: &Program<'c>) -> Vec<&'c str> {
+    let mut roots: Vec<&str> = program.runs.iter().map(|r| r.state).collect();
+    roots.extend(program.spec.iter().map(|s| s.state));
+    if roots.is_empty() {
+        roots.extend(program.program.iter().map(|i| i.state));
+    }
+    let mut states: Vec<&str> = Vec::new();
+    for root in roots {
+        if !states.contains(&root) {
+            states.push(root);
+        }
+    }
+
+    let mut i = 0;
+    while i < states.len() {
+        if program.halt_syms.contains(&states[i]) {
+            i += 1;
+            continue;
+        }
+        let state = states[i];
+        for instr in program.program.iter().filter(|instr| instr.state == state) {
+            if !states.contains(&instr.next_state) {
+                states.push(instr.next_state);
+            }
+        }
+        i += 1;
+    }
+    states
+}
+
+/// Merges behaviourally equivalent states and drops the rules of unreachable states.
+///
+/// States are split into blocks by partition refinement, like DFA minimisation:
+/// every halt state starts in a block of its own and the other states are split until
+/// all states of a block write, move and go to the same block for every symbol.
+/// Each block is then replaced by its first state in source order.
+pub fn minimize(mut program: Program<'_>) -> Program<'_> {
+    let mut states = reachable(&program);
+    // keep the source order so the representative of a block is its first state
+    let order = |s: &str| program.program.iter().position(|i| i.state == s);
+    states.sort_by_key(|&s| order(s).unwrap_or(usize::MAX));
+    let index: HashMap<&str, usize> = states.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+
+    // every symbol the rules were expanded for, a state without a rule for one of them
+    // stops there and cannot be merged with a state that has one
+    let mut syms = program.symbols();
+    for instr in program.program.iter() {
+        for sym in [instr.read, instr.write] {
+            if !syms.contains(&sym) {
+                syms.push(sym);
+            }
+        }
+    }
+    let is_halt = |s: &str| program.halt_syms.contains(&s);
+    // halt states never apply their rules
+    let table: Vec<Vec<Action>> = states
+        .iter()
+        .map(|&state| {
+            syms.iter()
+                .map(|&read| {
+                    program
+                        .lookup(state, read)
+                        .filter(|_| !is_halt(state))
+                        .map(|i| (i.write, i.dir, index[i.next_state]))
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut block: Vec<usize> = states
+        .iter()
+        .enumerate()
+        .map(|(i, s)| if is_halt(s) { i + 1 } else { 0 })
+        .collect();
+    loop {
+        let mut blocks: HashMap<(usize, Vec<Action>), usize> = HashMap::new();
+        let next: Vec<usize> = table
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let sig = row
+                    .iter()
+                    .map(|t| t.map(|(write, dir, next)| (write, dir, block[next])))
+                    .collect();
+                let len = blocks.len();
+                *blocks.entry((block[i], sig)).or_insert(len)
+            })
+            .collect();
+
+        // refinement only splits blocks, so the same count means the same partition
+        let stable = blocks.len() == block.iter().collect::<HashSet<_>>().len();
+        block = next;
+        if stable {
+            break;
+        }
+    }
+
+    let repr = |s: &str| -> usize { block.iter().position(|&b| b == block[index[s]]).unwrap() };
+    let mut table = Vec::new();
+    for (i, &state) in states.iter().enumerate() {
+        if repr(state) != i || is_halt(state) {
+            continue;
+        }
+        for instr in program.used_rules().filter(|instr| instr.state == state) {
+            table.push(Instr {
+                state,
+                read: instr.read,
+                write: instr.write,
+                dir: instr.dir,
+                next_state: states[repr(instr.next_state)],
+            });
+        }
+    }
+
+    for run in program.runs.iter_mut() {
+        run.state = states[repr(run.state)];
+    }
+    if let Some(spec) = program.spec.as_mut() {
+        spec.state = states[repr(spec.state)];
+    }
+    program.rules = table
+        .iter()
+        .map(|i| Instr {
+            state: i.state,
+            read: Pat::Sym(i.read),
+            write: Pat::Sym(i.write),
+            dir: i.dir,
+            next_state: i.next_state,
+        })
+        .collect();
+    program.program = table;
+    program
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interp::Machine;
+    use crate::parser::parse_source;
+
+    fn parse(src: &'static str) -> Program<'static> {
+        parse_source(src.as_bytes(), "test.tur").unwrap()
+    }
+
+    /// the states that have rules, in order
+    fn states<'c>(program: &Program<'c>) -> Vec<&'c str> {
+        let mut states = Vec::new();
+        for instr in program.program.iter() {
+            if !states.contains(&instr.state) {
+                states.push(instr.state);
+            }
+        }
+        states
+    }
+
+    /// runs every `#run` of both programs and checks they end with the same tape
+    fn same_runs(a: &Program<'_>, b: &Program<'_>) {
+        for (run_a, run_b) in a.runs.iter().zip(b.runs.iter()) {
+            let (mut ma, mut mb) = (Machine::new(a, run_a), Machine::new(b, run_b));
+            assert_eq!(ma.run(Some(1000)), mb.run(Some(1000)), "{run_a}");
+            assert_eq!((ma.tape, ma.head, ma.steps), (mb.tape, mb.head, mb.steps), "{run_a}");
+        }
+    }
+
+    #[test]
+    fn merges_equivalent_states() {
+        let src = "#halt H\n\
+                   #run [0 0 0 1] A\n\
+                   A 0 1 -> B\n\
+                   A 1 1 - H\n\
+                   B 0 1 -> C\n\
+                   B 1 1 - H\n\
+                   C 0 1 -> A\n\
+                   C 1 1 - H\n";
+        let opt = minimize(parse(src));
+        assert_eq!(states(&opt), ["A"]);
+        assert_eq!(opt.lookup("A", "0").map(|i| i.next_state), Some("A"));
+        same_runs(&parse(src), &opt);
+    }
+
+    #[test]
+    fn keeps_states_that_differ_later() {
+        // B and C only differ in the state they go to on 0, which differ on 1
+        let src = "#halt H\n\
+                   #run [0 0 0 1] A\n\
+                   A 0 0 -> B\n\
+                   B 0 0 -> C\n\
+                   C 0 0 -> D\n\
+                   D 0 0 -> D\n\
+                   B 1 1 - H\n\
+                   C 1 1 - H\n\
+                   D 1 0 - H\n";
+        let opt = minimize(parse(src));
+        assert_eq!(states(&opt), ["A", "B", "C", "D"]);
+        same_runs(&parse(src), &opt);
+    }
+
+    #[test]
+    fn missing_transition_is_an_outcome() {
+        // B has no rule for 1, so it stops where A goes on
+        let src = "#halt H\n\
+                   #run [0 1 1] A\n\
+                   A 0 0 -> B\n\
+                   A 1 1 -> A\n\
+                   B 0 0 -> B\n";
+        let opt = minimize(parse(src));
+        assert_eq!(states(&opt), ["A", "B"]);
+        assert!(opt.lookup("B", "1").is_none());
+        same_runs(&parse(src), &opt);
+    }
+
+    #[test]
+    fn keeps_halt_states_apart() {
+        let src = "#accept Y\n\
+                   #reject N\n\
+                   #run [0] A\n\
+                   A 0 0 - Y\n\
+                   A 1 1 - N\n";
+        let opt = minimize(parse(src));
+        assert_eq!(opt.lookup("A", "0").map(|i| i.next_state), Some("Y"));
+        assert_eq!(opt.lookup("A", "1").map(|i| i.next_state), Some("N"));
+    }
+
+    #[test]
+    fn drops_unreachable_states() {
+        let src = "#halt H\n\
+                   #run [0] A\n\
+                   A 0 1 - H\n\
+                   U 0 1 -> U\n";
+        let opt = minimize(parse(src));
+        assert_eq!(states(&opt), ["A"]);
+    }
+}
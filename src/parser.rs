@@ -1,11 +1,35 @@
-use crate::lexer::{Lexer, Token, TokenKind, TokenResult};
+use crate::bb;
+use crate::lexer::{self, Lexer, Loc, Quoted, Shown, Token, TokenKind, TokenResult};
 use std::error::Error;
 use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Dir {
     Left,
     Right,
+    Stay,
+}
+
+impl Dir {
+    /// the name used in JSON traces and programs
+    pub fn name(self) -> &'static str {
+        match self {
+            Dir::Left => "left",
+            Dir::Right => "right",
+            Dir::Stay => "stay",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Dir::Left),
+            "right" => Some(Dir::Right),
+            "stay" => Some(Dir::Stay),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Dir {
@@ -13,15 +37,42 @@ impl fmt::Display for Dir {
         let dir = match self {
             Dir::Left => "<-",
             Dir::Right => "->",
+            Dir::Stay => "-",
         };
         write!(f, "{dir}")
     }
 }
 
+/// a read or write position of a rule as written in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pat<'c> {
+    Sym(&'c str),
+    /// `$NAME`, matches the symbols of a `#set`
+    Set(&'c str),
+    /// `*`, matches any symbol that is not handled by another rule of the same state
+    Any,
+    /// `=`, writes back the symbol that was read
+    Same,
+}
+
+impl<'c> fmt::Display for Pat<'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pat::Sym(s) => write!(f, "{s}"),
+            Pat::Set(name) => write!(f, "${name}"),
+            Pat::Any => write!(f, "*"),
+            Pat::Same => write!(f, "="),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RunCmd<'c> {
     pub tape: Vec<&'c str>,
+    /// the cell the head starts on
+    pub head: usize,
     pub state: &'c str,
+    pub expect: Option<ExpectCmd<'c>>,
 }
 
 impl<'c> fmt::Display for RunCmd<'c> {
@@ -29,56 +80,307 @@ impl<'c> fmt::Display for RunCmd<'c> {
         write!(f, "#run [ ")?;
         let (last, t) = self.tape.split_last().expect("tape cannot be empty");
         for t in t {
-            write!(f, "{t} ")?;
+            write!(f, "{} ", Shown(t))?;
+        }
+        write!(f, "{} ] {state}", Shown(last), state = self.state)?;
+        if self.head > 0 {
+            write!(f, " @{}", self.head)?;
         }
-        write!(f, "{last} ] {state}", state = self.state)?;
         Ok(())
     }
 }
+
+/// `#run bb "<NOTATION>"`, a machine in busy beaver notation that runs on its own
+#[derive(Debug)]
+pub struct BbRunCmd<'c> {
+    pub notation: &'c str,
+    pub table: bb::Table,
+    /// the number of tape runs before it in the source
+    pub at: usize,
+}
+
+/// a tape run or a `#run bb`, see [`Program::all_runs`]
+pub enum AnyRun<'a, 'c> {
+    Tape(&'a RunCmd<'c>),
+    Bb(&'a BbRunCmd<'c>),
+}
+
+pub enum Run<'c> {
+    Tape(RunCmd<'c>),
+    /// `#run @<FILE> <STATE>`, a run per tape of the file
+    File(Vec<RunCmd<'c>>),
+    Bb(BbRunCmd<'c>),
+}
+
+/// the configuration a `#run` is expected to halt in
+#[derive(Debug)]
+pub struct ExpectCmd<'c> {
+    pub tape: Vec<&'c str>,
+    pub state: &'c str,
+    pub head: Option<usize>,
+    pub steps: Option<usize>,
+}
+
+impl<'c> fmt::Display for ExpectCmd<'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#expect [ ")?;
+        for t in self.tape.iter() {
+            write!(f, "{} ", Shown(t))?;
+        }
+        write!(f, "] {state}", state = self.state)?;
+        if let Some(head) = self.head {
+            write!(f, " @{head}")?;
+        }
+        if let Some(steps) = self.steps {
+            write!(f, " {steps}")?;
+        }
+        Ok(())
+    }
+}
+
+/// inputs that `turir verify` enumerates: every word over `alphabet`
+/// with at most `max_len` symbols, followed by a `blank` cell
+#[derive(Debug)]
+pub struct SpecCmd<'c> {
+    pub alphabet: Vec<&'c str>,
+    pub max_len: usize,
+    pub state: &'c str,
+    pub blank: &'c str,
+}
+
 #[derive(Debug)]
 pub struct HaltCmd<'c> {
     pub states: Vec<&'c str>,
 }
 
 #[derive(Debug)]
-pub struct ParseErr<'c, 'k> {
-    pub expected: &'k [TokenKind],
-    pub got: TokenResult<'c>,
+pub struct SetCmd<'c> {
+    pub name: &'c str,
+    pub syms: Vec<&'c str>,
+}
+
+#[derive(Debug)]
+pub struct MacroCmd<'c> {
+    pub name: &'c str,
+    pub params: Vec<&'c str>,
+    pub rules: Vec<Instr<&'c str, Pat<'c>>>,
+    pub loc: Loc,
+}
+
+#[derive(Debug)]
+pub enum ParseErr<'c, 'k> {
+    Unexpected {
+        expected: &'k [TokenKind],
+        got: TokenResult<'c>,
+    },
+    UndefinedSet {
+        name: &'c str,
+        loc: Loc,
+    },
+    SetRedefined {
+        name: &'c str,
+        loc: Loc,
+    },
+    UnknownCmd {
+        name: &'c str,
+        loc: Loc,
+    },
+    UndefinedMacro {
+        name: &'c str,
+        loc: Loc,
+    },
+    MacroRedefined {
+        name: &'c str,
+        loc: Loc,
+        def: Loc,
+    },
+    MacroArity {
+        name: &'c str,
+        expected: usize,
+        got: usize,
+        loc: Loc,
+        def: Loc,
+    },
+    Include {
+        path: &'c str,
+        loc: Loc,
+        err: io::Error,
+    },
+    TapeFile {
+        path: &'c str,
+        loc: Loc,
+        err: io::Error,
+    },
+    IncludeCycle {
+        path: &'c str,
+        loc: Loc,
+    },
+    UnknownNamespace {
+        path: &'c str,
+        loc: Loc,
+    },
+    NamespaceTaken {
+        name: &'c str,
+        loc: Loc,
+    },
+    ExpectWithoutRun {
+        loc: Loc,
+    },
+    InvalidExpect {
+        text: &'c str,
+        loc: Loc,
+    },
+    InvalidNumber {
+        text: &'c str,
+        loc: Loc,
+    },
+    EmptyTape {
+        loc: Loc,
+    },
+    HeadMarkedTwice {
+        loc: Loc,
+    },
+    HeadOutsideTape {
+        head: usize,
+        len: usize,
+        loc: Loc,
+    },
+    InvalidUtf8 {
+        loc: Loc,
+    },
+    Bb {
+        err: bb::BbErr,
+        loc: Loc,
+    },
 }
 impl<'c, 'k> Error for ParseErr<'c, 'k> {}
 
 impl<'c, 'k> fmt::Display for ParseErr<'c, 'k> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let loc = match &self.got {
+        let (expected, got) = match self {
+            ParseErr::Unexpected { expected, got } => (expected, got),
+            ParseErr::UndefinedSet { name, loc } => {
+                return write!(f, "{loc}: Set `${name}` is not defined");
+            }
+            ParseErr::SetRedefined { name, loc } => {
+                return write!(f, "{loc}: Set `${name}` is already defined");
+            }
+            ParseErr::UnknownCmd { name, loc } => {
+                return write!(f, "{loc}: Unknown command `{name}`");
+            }
+            ParseErr::UndefinedMacro { name, loc } => {
+                return write!(f, "{loc}: Macro `{name}` is not defined");
+            }
+            ParseErr::MacroRedefined { name, loc, def } => {
+                return write!(
+                    f,
+                    "{loc}: Macro `{name}` is already defined\n{def}: previous definition is here"
+                );
+            }
+            ParseErr::MacroArity {
+                name,
+                expected,
+                got,
+                loc,
+                def,
+            } => {
+                return write!(
+                    f,
+                    "{loc}: Macro `{name}` takes {expected} arguments but {got} were given\n\
+                     {def}: macro is defined here"
+                );
+            }
+            ParseErr::Include { path, loc, err } => {
+                return write!(f, "{loc}: Could not include \"{path}\": {err}");
+            }
+            ParseErr::TapeFile { path, loc, err } => {
+                return write!(f, "{loc}: Could not read tapes from \"{path}\": {err}");
+            }
+            ParseErr::IncludeCycle { path, loc } => {
+                return write!(f, "{loc}: Including \"{path}\" creates a cycle");
+            }
+            ParseErr::UnknownNamespace { path, loc } => {
+                return write!(f, "{loc}: Could not derive a namespace from \"{path}\"");
+            }
+            ParseErr::NamespaceTaken { name, loc } => {
+                return write!(f, "{loc}: Namespace `{name}` is already included");
+            }
+            ParseErr::ExpectWithoutRun { loc } => {
+                return write!(f, "{loc}: `#expect` must follow a `#run` without an expectation");
+            }
+            ParseErr::InvalidNumber { text, loc } => {
+                return write!(f, "{loc}: Expected a number but got `{text}`");
+            }
+            ParseErr::InvalidUtf8 { loc } => {
+                return write!(f, "{loc}: Source is not valid UTF-8");
+            }
+            ParseErr::EmptyTape { loc } => {
+                return write!(f, "{loc}: A machine cannot start on an empty tape");
+            }
+            ParseErr::HeadMarkedTwice { loc } => {
+                return write!(f, "{loc}: The head can only start on one cell");
+            }
+            ParseErr::HeadOutsideTape { head, len, loc } => {
+                return write!(f, "{loc}: Head @{head} is outside of the tape of {len} cells");
+            }
+            ParseErr::Bb { err, loc } => {
+                return write!(f, "{loc}: Invalid busy beaver machine: {err}");
+            }
+            ParseErr::InvalidExpect { text, loc } => {
+                return write!(
+                    f,
+                    "{loc}: Expected `@<HEAD>` or a step count but got `{text}`"
+                );
+            }
+        };
+        let loc = match got {
             TokenResult::Eof { loc } => loc,
             TokenResult::Valid(Token { loc, .. }) => loc,
             TokenResult::Unknown { loc, .. } => loc,
             TokenResult::UnclosedStr { loc } => loc,
+            TokenResult::InvalidEscape { loc, .. } => loc,
         };
         write!(f, "{loc}: Expected ")?;
 
-        let (last, ks) = self.expected.split_last().unwrap();
+        let (last, ks) = expected.split_last().unwrap();
         for k in ks.iter() {
             write!(f, "{} or ", k.to_str())?;
         }
         write!(f, "{} but got ", last.to_str())?;
-        match &self.got {
+        match got {
             TokenResult::Eof { .. } => write!(f, "EOF"),
             TokenResult::Valid(Token { text, kind, .. }) => {
                 write!(f, "{} {:?}", kind.to_str(), text)
             }
             TokenResult::Unknown { text, .. } => write!(f, "unknown token `{}`", text),
             TokenResult::UnclosedStr { .. } => write!(f, "unclosed string"),
+            TokenResult::InvalidEscape { text, .. } => write!(f, "invalid escape `{text}`"),
         }
     }
 }
 
 pub struct Parser<'c> {
     lexer: Lexer<'c>,
+    pub sets: Vec<SetCmd<'c>>,
+    pub macros: Vec<MacroCmd<'c>>,
+    instances: usize,
 }
 impl<'c> Parser<'c> {
     pub fn new(lexer: Lexer<'c>) -> Self {
-        Self { lexer }
+        Self {
+            lexer,
+            sets: Vec::new(),
+            macros: Vec::new(),
+            instances: 0,
+        }
+    }
+
+    fn lookup_set<'k>(&self, tok: &Token<'c>) -> Result<&SetCmd<'c>, ParseErr<'c, 'k>> {
+        let name = &tok.text[1..];
+        match self.sets.iter().find(|s| s.name == name) {
+            Some(set) => Ok(set),
+            None => Err(ParseErr::UndefinedSet { name, loc: tok.loc }),
+        }
     }
 
     pub fn peek_token(&mut self) -> TokenResult<'c> {
@@ -91,25 +393,33 @@ impl<'c> Parser<'c> {
 
     fn expect_token<'k>(&mut self, kinds: &'k [TokenKind]) -> Result<Token<'c>, ParseErr<'c, 'k>> {
         match self.lexer.next_token() {
-            TokenResult::Valid(tok @ Token { kind, .. }) if kinds.iter().any(|&k| kind == k) => {
+            TokenResult::Valid(tok @ Token { kind, .. }) if kinds.contains(&kind) => {
                 Ok(tok)
             }
-            tr => Err(ParseErr {
+            tr => Err(ParseErr::Unexpected {
                 expected: kinds,
                 got: tr,
             })?,
         }
     }
 
-    pub fn parse_instr<'k>(&mut self) -> Result<Instr<&'c str, &'c str>, ParseErr<'c, 'k>> {
+    pub fn parse_instr<'k>(&mut self) -> Result<Instr<&'c str, Pat<'c>>, ParseErr<'c, 'k>> {
         use TokenKind::*;
         let state = self.expect_token(&[Symbol])?.text;
-        let read = self.expect_token(&[Symbol])?.text;
-        let write = self.expect_token(&[Symbol])?.text;
+        let read = match self.expect_token(&[Symbol, SetRef, Wildcard])? {
+            Token { kind: Wildcard, .. } => Pat::Any,
+            tok @ Token { kind: SetRef, .. } => Pat::Set(self.lookup_set(&tok)?.name),
+            Token { text, .. } => Pat::Sym(text),
+        };
+        let write = match self.expect_token(&[Symbol, Same])? {
+            Token { kind: Same, .. } => Pat::Same,
+            Token { text, .. } => Pat::Sym(text),
+        };
 
-        let dir = match self.expect_token(&[LeftArrow, RightArrow])?.kind {
+        let dir = match self.expect_token(&[LeftArrow, RightArrow, Stay])?.kind {
             LeftArrow => Dir::Left,
             RightArrow => Dir::Right,
+            Stay => Dir::Stay,
             _ => unreachable!(),
         };
 
@@ -124,11 +434,18 @@ impl<'c> Parser<'c> {
         })
     }
 
-    pub fn parse_cmd_run<'k>(&mut self) -> Result<RunCmd<'c>, ParseErr<'c, 'k>> {
+    /// Parses the cells of a tape in brackets, or a string with a cell per character.
+    fn parse_tape<'k>(&mut self) -> Result<Vec<&'c str>, ParseErr<'c, 'k>> {
         use TokenKind::*;
 
-        let _ = self.expect_token(&[Cmd])?;
-        let _ = self.expect_token(&[Bra])?;
+        let token = self.expect_token(&[Bra, Str])?;
+        if token.kind == Str {
+            let text = token.text;
+            return Ok(text
+                .char_indices()
+                .map(|(i, c)| &text[i..i + c.len_utf8()])
+                .collect());
+        }
 
         let mut tape = Vec::new();
         loop {
@@ -139,9 +456,412 @@ impl<'c> Parser<'c> {
                 _ => unreachable!(),
             }
         }
+        Ok(tape)
+    }
+
+    /// Parses cells up to the last of `kinds`, a `>` in front of a cell marks it as the one the head starts on.
+    fn parse_marked_cells<'k>(
+        &mut self,
+        kinds: &'k [TokenKind],
+    ) -> Result<(Vec<&'c str>, Option<usize>), ParseErr<'c, 'k>> {
+        use TokenKind::*;
+
+        let (mut tape, mut head) = (Vec::new(), None);
+        loop {
+            let token = self.expect_token(kinds)?;
+            match token.kind {
+                Symbol => tape.push(token.text),
+                Head => {
+                    if head.replace(tape.len()).is_some() {
+                        return Err(ParseErr::HeadMarkedTwice { loc: token.loc });
+                    }
+                    tape.push(self.expect_token(&[Symbol])?.text);
+                }
+                _ => break,
+            }
+        }
+        Ok((tape, head))
+    }
+
+    /// The tape a machine starts on, which has at least one cell,
+    /// along with the cell marked as the start of the head if there is one.
+    fn parse_initial_tape<'k>(
+        &mut self,
+    ) -> Result<(Vec<&'c str>, Option<usize>, Loc), ParseErr<'c, 'k>> {
+        use TokenKind::*;
+
+        let (tape, head, loc) = match self.peek_token() {
+            TokenResult::Valid(Token { kind: Bra, loc, .. }) => {
+                self.skip_token();
+                let (tape, head) = self.parse_marked_cells(&[Symbol, Head, Ket])?;
+                (tape, head, loc)
+            }
+            TokenResult::Valid(Token { loc, .. }) => (self.parse_tape()?, None, loc),
+            _ => (self.parse_tape()?, None, self.lexer.loc()),
+        };
+        if tape.is_empty() {
+            return Err(ParseErr::EmptyTape { loc });
+        }
+        Ok((tape, head, loc))
+    }
+
+    pub fn parse_cmd_run<'k>(&mut self) -> Result<Run<'c>, ParseErr<'c, 'k>> {
+        use TokenKind::*;
+
+        let _ = self.expect_token(&[Cmd])?;
+        if let TokenResult::Valid(Token {
+            kind: Symbol,
+            text: "bb",
+            ..
+        }) = self.peek_token()
+        {
+            self.skip_token();
+            let Token {
+                text: notation,
+                loc,
+                ..
+            } = self.expect_token(&[Str])?;
+            let table = bb::Table::parse(notation).map_err(|err| ParseErr::Bb { err, loc })?;
+            let _ = self.expect_token(&[NewLine])?;
+            return Ok(Run::Bb(BbRunCmd {
+                notation,
+                table,
+                at: 0,
+            }));
+        }
+        if let TokenResult::Valid(Token {
+            kind: Symbol,
+            text,
+            loc,
+        }) = self.peek_token()
+        {
+            if let Some(path) = text.strip_prefix('@') {
+                self.skip_token();
+                let state = self.expect_token(&[Symbol])?.text;
+                let _ = self.expect_token(&[NewLine])?;
+
+                let file = self.relative(path);
+                let content = match read_source(&file.to_string_lossy()) {
+                    Ok(c) => Box::leak(c.into_boxed_slice()),
+                    Err(err) => return Err(ParseErr::TapeFile { path, loc, err }),
+                };
+                let file: &'static str = Box::leak(file.to_string_lossy().into_owned().into_boxed_str());
+                let runs = parse_tapes(content, file)?
+                    .into_iter()
+                    .map(|(tape, head)| RunCmd {
+                        tape,
+                        head,
+                        state,
+                        expect: None,
+                    })
+                    .collect();
+                return Ok(Run::File(runs));
+            }
+        }
+
+        let (tape, marked, loc) = self.parse_initial_tape()?;
+        let state = self.expect_token(&[Symbol])?.text;
+        let (mut head, mut loc) = (marked.unwrap_or(0), loc);
+        let token = self.expect_token(&[NewLine, Symbol])?;
+        if token.kind == Symbol {
+            let text = token.text;
+            loc = token.loc;
+            head = match text.strip_prefix('@').map(str::parse) {
+                Some(Ok(n)) => n,
+                _ => return Err(ParseErr::InvalidNumber { text, loc }),
+            };
+            if marked.is_some() {
+                return Err(ParseErr::HeadMarkedTwice { loc });
+            }
+            let _ = self.expect_token(&[NewLine])?;
+        }
+        if head >= tape.len() {
+            let len = tape.len();
+            return Err(ParseErr::HeadOutsideTape { head, len, loc });
+        }
+        Ok(Run::Tape(RunCmd {
+            tape,
+            head,
+            state,
+            expect: None,
+        }))
+    }
+
+    pub fn parse_cmd_expect<'k>(&mut self) -> Result<ExpectCmd<'c>, ParseErr<'c, 'k>> {
+        use TokenKind::*;
+
+        let _ = self.expect_token(&[Cmd])?;
+        let tape = self.parse_tape()?;
+        let state = self.expect_token(&[Symbol])?.text;
+
+        let (mut head, mut steps) = (None, None);
+        loop {
+            let token = self.expect_token(&[NewLine, Symbol])?;
+            let (text, loc) = match token.kind {
+                Symbol => (token.text, token.loc),
+                NewLine => break,
+                _ => unreachable!(),
+            };
+            let (slot, n) = match text.strip_prefix('@') {
+                Some(n) => (&mut head, n),
+                None => (&mut steps, text),
+            };
+            match n.parse() {
+                Ok(n) if slot.is_none() => *slot = Some(n),
+                _ => return Err(ParseErr::InvalidExpect { text, loc }),
+            }
+        }
+
+        Ok(ExpectCmd {
+            tape,
+            state,
+            head,
+            steps,
+        })
+    }
+
+    pub fn parse_cmd_spec<'k>(&mut self) -> Result<SpecCmd<'c>, ParseErr<'c, 'k>> {
+        use TokenKind::*;
+
+        let _ = self.expect_token(&[Cmd])?;
+        let alphabet = self.parse_tape()?;
+        let Token { text, loc, .. } = self.expect_token(&[Symbol])?;
+        let Ok(max_len) = text.parse() else {
+            return Err(ParseErr::InvalidNumber { text, loc });
+        };
         let state = self.expect_token(&[Symbol])?.text;
+
+        let token = self.expect_token(&[NewLine, Symbol])?;
+        let blank = match token.kind {
+            Symbol => {
+                let _ = self.expect_token(&[NewLine])?;
+                token.text
+            }
+            _ => match alphabet.first() {
+                Some(&blank) => blank,
+                None => return Err(ParseErr::Unexpected {
+                    expected: &[Symbol],
+                    got: TokenResult::Valid(token),
+                }),
+            },
+        };
+
+        Ok(SpecCmd {
+            alphabet,
+            max_len,
+            state,
+            blank,
+        })
+    }
+
+    pub fn parse_cmd_set<'k>(&mut self) -> Result<SetCmd<'c>, ParseErr<'c, 'k>> {
+        use TokenKind::*;
+
+        let _ = self.expect_token(&[Cmd])?;
+        let Token { text: name, loc, .. } = self.expect_token(&[Symbol])?;
+        if self.sets.iter().any(|s| s.name == name) {
+            return Err(ParseErr::SetRedefined { name, loc });
+        }
+
+        let mut syms: Vec<&str> = Vec::new();
+        loop {
+            let token = self.expect_token(&[NewLine, Symbol, SetRef])?;
+            match token.kind {
+                Symbol => syms.push(token.text),
+                SetRef => syms.extend(self.lookup_set(&token)?.syms.iter()),
+                NewLine => break,
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(SetCmd { name, syms })
+    }
+
+    /// Parses the symbols of an `#alphabet` or `#input` line, sets are replaced by their symbols.
+    pub fn parse_cmd_alphabet<'k>(&mut self) -> Result<Vec<&'c str>, ParseErr<'c, 'k>> {
+        use TokenKind::*;
+
+        let _ = self.expect_token(&[Cmd])?;
+
+        let mut syms: Vec<&str> = Vec::new();
+        loop {
+            let token = self.expect_token(&[NewLine, Symbol, SetRef])?;
+            let new = match token.kind {
+                Symbol => vec![token.text],
+                SetRef => self.lookup_set(&token)?.syms.clone(),
+                NewLine => break,
+                _ => unreachable!(),
+            };
+            for sym in new {
+                if !syms.contains(&sym) {
+                    syms.push(sym);
+                }
+            }
+        }
+
+        Ok(syms)
+    }
+
+    pub fn parse_cmd_macro<'k>(&mut self) -> Result<MacroCmd<'c>, ParseErr<'c, 'k>> {
+        use TokenKind::*;
+
+        let _ = self.expect_token(&[Cmd])?;
+        let Token { text: name, loc, .. } = self.expect_token(&[Symbol])?;
+        if let Some(m) = self.macros.iter().find(|m| m.name == name) {
+            return Err(ParseErr::MacroRedefined {
+                name,
+                loc,
+                def: m.loc,
+            });
+        }
+
+        let mut params = Vec::new();
+        loop {
+            let token = self.expect_token(&[NewLine, Symbol])?;
+            match token.kind {
+                Symbol => params.push(token.text),
+                NewLine => break,
+                _ => unreachable!(),
+            }
+        }
+
+        let mut rules = Vec::new();
+        loop {
+            match self.peek_token() {
+                TokenResult::Valid(Token { kind: NewLine, .. }) => self.skip_token(),
+                TokenResult::Valid(Token {
+                    kind: Cmd,
+                    text: "#end",
+                    ..
+                }) => {
+                    self.skip_token();
+                    let _ = self.expect_token(&[NewLine])?;
+                    break;
+                }
+                TokenResult::Valid(Token { kind: Symbol, .. }) => rules.push(self.parse_instr()?),
+                got => {
+                    return Err(ParseErr::Unexpected {
+                        expected: &[Symbol, Cmd],
+                        got,
+                    })
+                }
+            }
+        }
+
+        Ok(MacroCmd {
+            name,
+            params,
+            rules,
+            loc,
+        })
+    }
+
+    /// Expands a `#use` line into the rules of a fresh macro instance.
+    /// Parameters are substituted by the arguments, states that are defined inside the macro body
+    /// are renamed to `<MACRO>#<N>::<STATE>` so every instance gets its own copy.
+    pub fn parse_cmd_use<'k>(&mut self) -> Result<Vec<Instr<&'c str, Pat<'c>>>, ParseErr<'c, 'k>> {
+        use TokenKind::*;
+
+        let _ = self.expect_token(&[Cmd])?;
+        let Token { text: name, loc, .. } = self.expect_token(&[Symbol])?;
+
+        let mut args = Vec::new();
+        loop {
+            let token = self.expect_token(&[NewLine, Symbol])?;
+            match token.kind {
+                Symbol => args.push(token.text),
+                NewLine => break,
+                _ => unreachable!(),
+            }
+        }
+
+        let Some(m) = self.macros.iter().find(|m| m.name == name) else {
+            return Err(ParseErr::UndefinedMacro { name, loc });
+        };
+        if m.params.len() != args.len() {
+            return Err(ParseErr::MacroArity {
+                name,
+                expected: m.params.len(),
+                got: args.len(),
+                loc,
+                def: m.loc,
+            });
+        }
+
+        self.instances += 1;
+        let mut locals: Vec<(&str, &'c str)> = Vec::new();
+        for rule in m.rules.iter() {
+            if !m.params.contains(&rule.state) && !locals.iter().any(|l| l.0 == rule.state) {
+                let fresh = format!("{name}#{}::{}", self.instances, rule.state);
+                locals.push((rule.state, Box::leak(fresh.into_boxed_str())));
+            }
+        }
+
+        let param = |s: &'c str| m.params.iter().position(|&p| p == s).map(|i| args[i]);
+        // local states are only renamed in state positions, a symbol with the same name stays as is
+        let subst = |s: &'c str| match param(s) {
+            Some(arg) => arg,
+            None => locals.iter().find(|l| l.0 == s).map_or(s, |l| l.1),
+        };
+        let subst_pat = |p: Pat<'c>| match p {
+            Pat::Sym(s) => Pat::Sym(param(s).unwrap_or(s)),
+            p => p,
+        };
+
+        Ok(m.rules
+            .iter()
+            .map(|rule| Instr {
+                state: subst(rule.state),
+                read: subst_pat(rule.read),
+                write: subst_pat(rule.write),
+                dir: rule.dir,
+                next_state: subst(rule.next_state),
+            })
+            .collect())
+    }
+
+    /// a path relative to the directory of the current file
+    fn relative(&self, path: &str) -> PathBuf {
+        Path::new(self.lexer.file())
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(path)
+    }
+
+    /// Parses the file of an `#include` line relative to the current file.
+    /// Returns the namespace of the included file along with its contents.
+    fn parse_cmd_include<'k>(
+        &mut self,
+        includes: &mut Vec<PathBuf>,
+    ) -> Result<(&'c str, Loc, Module<'c>), ParseErr<'c, 'k>> {
+        use TokenKind::*;
+
+        let Token { loc, .. } = self.expect_token(&[Cmd])?;
+        let Token { text: path, .. } = self.expect_token(&[Str])?;
         let _ = self.expect_token(&[NewLine])?;
-        Ok(RunCmd { tape, state })
+
+        let file = self.relative(path);
+        let ns = match file.file_stem().and_then(|s| s.to_str()) {
+            Some(ns) => Box::leak(ns.to_string().into_boxed_str()),
+            None => return Err(ParseErr::UnknownNamespace { path, loc }),
+        };
+
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+        if includes.contains(&canonical) {
+            return Err(ParseErr::IncludeCycle { path, loc });
+        }
+
+        let content = match read_source(&file.to_string_lossy()) {
+            Ok(c) => Box::leak(c.into_boxed_slice()),
+            Err(err) => return Err(ParseErr::Include { path, loc, err }),
+        };
+        let file: &'static str = Box::leak(file.to_string_lossy().into_owned().into_boxed_str());
+
+        includes.push(canonical);
+        let module = parse_module(content, file, includes)?;
+        includes.pop();
+
+        Ok((ns, loc, module))
     }
 
     pub fn parse_cmd_halt<'k>(&mut self) -> Result<HaltCmd<'c>, ParseErr<'c, 'k>> {
@@ -165,11 +885,226 @@ impl<'c> Parser<'c> {
 
 pub struct Program<'c> {
     pub runs: Vec<RunCmd<'c>>,
+    pub bb_runs: Vec<BbRunCmd<'c>>,
     pub halt_syms: Vec<&'c str>,
+    /// halt states of `#accept` and `#reject`, they are in `halt_syms` as well
+    pub accept_syms: Vec<&'c str>,
+    pub reject_syms: Vec<&'c str>,
+    pub spec: Option<SpecCmd<'c>>,
+    /// the symbols declared with `#alphabet`, every other symbol is rejected by `turir check`
+    pub alphabet: Option<Vec<&'c str>>,
+    /// the symbols declared with `#input`, that the tapes of runs are written with
+    pub input: Option<Vec<&'c str>>,
+    pub sets: Vec<SetCmd<'c>>,
+    /// rules as written in the source, before wildcard expansion
+    pub rules: Vec<Instr<&'c str, Pat<'c>>>,
     pub program: Vec<Instr<&'c str, &'c str>>,
 }
 
-#[derive(Debug)]
+impl<'c> Program<'c> {
+    /// The rule that is applied in `state` on `read`. Only the first rule of a (state, read) pair
+    /// is ever used, `turir check` reports the others.
+    pub fn lookup(&self, state: &str, read: &str) -> Option<&Instr<&'c str, &'c str>> {
+        self.program
+            .iter()
+            .find(|instr| instr.state == state && instr.read == read)
+    }
+
+    /// the rules of `program` that [`Program::lookup`] can return, in source order
+    pub fn used_rules(&self) -> impl Iterator<Item = &Instr<&'c str, &'c str>> {
+        self.program.iter().filter(|instr| {
+            self.lookup(instr.state, instr.read)
+                .is_some_and(|used| std::ptr::eq(used, *instr))
+        })
+    }
+
+    /// the tape runs and `#run bb`s in source order
+    pub fn all_runs(&self) -> Vec<AnyRun<'_, 'c>> {
+        let mut all = Vec::new();
+        let mut bb_runs = self.bb_runs.iter().peekable();
+        for (i, run) in self.runs.iter().enumerate() {
+            while let Some(bb) = bb_runs.next_if(|bb| bb.at <= i) {
+                all.push(AnyRun::Bb(bb));
+            }
+            all.push(AnyRun::Tape(run));
+        }
+        all.extend(bb_runs.map(AnyRun::Bb));
+        all
+    }
+
+    pub fn is_accept(&self, state: &str) -> bool {
+        self.accept_syms.contains(&state)
+    }
+
+    pub fn is_reject(&self, state: &str) -> bool {
+        self.reject_syms.contains(&state)
+    }
+
+    /// the symbols a `*` rule matches: the `#alphabet` if it is declared,
+    /// otherwise every symbol of the rules, runs and `#spec`
+    pub fn symbols(&self) -> Vec<&'c str> {
+        if let Some(alphabet) = &self.alphabet {
+            return alphabet.clone();
+        }
+        let mut syms = tape_symbols(&self.rules, &self.runs);
+        if let Some(spec) = &self.spec {
+            for &sym in spec.alphabet.iter().chain([&spec.blank]) {
+                if !syms.contains(&sym) {
+                    syms.push(sym);
+                }
+            }
+        }
+        syms
+    }
+
+    /// Expands the rules into `program` again, `*` rules also match the symbols of `extra`.
+    /// Tapes that are not part of the source, like `--input` tapes, can have symbols
+    /// that the rules have not been expanded for.
+    pub fn expand(&mut self, extra: &[&'c str]) {
+        let mut syms = self.symbols();
+        for &sym in extra {
+            if !syms.contains(&sym) {
+                syms.push(sym);
+            }
+        }
+        self.program = expand_rules(&self.rules, &syms, &self.sets);
+    }
+
+    /// a program with only a transition table, as read from other formats
+    pub fn from_table(
+        runs: Vec<RunCmd<'c>>,
+        mut halt_syms: Vec<&'c str>,
+        spec: Option<SpecCmd<'c>>,
+        program: Vec<Instr<&'c str, &'c str>>,
+    ) -> Self {
+        if halt_syms.is_empty() {
+            halt_syms.push("HALT");
+        }
+        let rules = program
+            .iter()
+            .map(|instr| Instr {
+                state: instr.state,
+                read: Pat::Sym(instr.read),
+                write: Pat::Sym(instr.write),
+                dir: instr.dir,
+                next_state: instr.next_state,
+            })
+            .collect();
+        Program {
+            runs,
+            bb_runs: Vec::new(),
+            halt_syms,
+            accept_syms: Vec::new(),
+            reject_syms: Vec::new(),
+            spec,
+            alphabet: None,
+            input: None,
+            sets: Vec::new(),
+            rules,
+            program,
+        }
+    }
+}
+
+/// Writes the program back in `.tur` syntax with its expanded transition table,
+/// so sets, macros and includes do not appear in the output.
+impl<'c> fmt::Display for Program<'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tape = |f: &mut fmt::Formatter, tape: &[&str]| {
+            write!(f, "[ ")?;
+            for t in tape {
+                write!(f, "{} ", Quoted(t))?;
+            }
+            write!(f, "]")
+        };
+
+        let halt: Vec<&str> = self
+            .halt_syms
+            .iter()
+            .filter(|s| !self.is_accept(s) && !self.is_reject(s))
+            .copied()
+            .collect();
+        for (cmd, syms) in [
+            ("#halt", &halt),
+            ("#accept", &self.accept_syms),
+            ("#reject", &self.reject_syms),
+        ] {
+            if !syms.is_empty() {
+                write!(f, "{cmd}")?;
+                for s in syms.iter() {
+                    write!(f, " {}", Quoted(s))?;
+                }
+                writeln!(f)?;
+            }
+        }
+        for (cmd, syms) in [("#alphabet", &self.alphabet), ("#input", &self.input)] {
+            if let Some(syms) = syms {
+                write!(f, "{cmd}")?;
+                for s in syms.iter() {
+                    write!(f, " {}", Quoted(s))?;
+                }
+                writeln!(f)?;
+            }
+        }
+
+        if let Some(spec) = &self.spec {
+            write!(f, "#spec ")?;
+            tape(f, &spec.alphabet)?;
+            writeln!(
+                f,
+                " {} {} {}",
+                spec.max_len,
+                Quoted(spec.state),
+                Quoted(spec.blank)
+            )?;
+        }
+
+        for run in self.all_runs() {
+            let run = match run {
+                AnyRun::Tape(run) => run,
+                AnyRun::Bb(run) => {
+                    writeln!(f, "#run bb \"{}\"", run.notation)?;
+                    continue;
+                }
+            };
+            write!(f, "#run ")?;
+            tape(f, &run.tape)?;
+            write!(f, " {}", Quoted(run.state))?;
+            if run.head > 0 {
+                write!(f, " @{}", run.head)?;
+            }
+            writeln!(f)?;
+            if let Some(expect) = &run.expect {
+                write!(f, "#expect ")?;
+                tape(f, &expect.tape)?;
+                write!(f, " {}", Quoted(expect.state))?;
+                if let Some(head) = expect.head {
+                    write!(f, " @{head}")?;
+                }
+                if let Some(steps) = expect.steps {
+                    write!(f, " {steps}")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        writeln!(f)?;
+
+        for instr in self.program.iter() {
+            writeln!(
+                f,
+                "{} {} {} {} {}",
+                Quoted(instr.state),
+                Quoted(instr.read),
+                Quoted(instr.write),
+                instr.dir,
+                Quoted(instr.next_state)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Instr<St, Sym> {
     pub state: St,
     pub read: Sym,
@@ -178,6 +1113,26 @@ pub struct Instr<St, Sym> {
     pub next_state: St,
 }
 
+impl<'c> Instr<&'c str, Pat<'c>> {
+    fn rename<S, T>(&self, state: S, set: T) -> Self
+    where
+        S: Fn(&'c str) -> &'c str,
+        T: Fn(&'c str) -> &'c str,
+    {
+        let pat = |p| match p {
+            Pat::Set(name) => Pat::Set(set(name)),
+            p => p,
+        };
+        Instr {
+            state: state(self.state),
+            read: pat(self.read),
+            write: pat(self.write),
+            dir: self.dir,
+            next_state: state(self.next_state),
+        }
+    }
+}
+
 impl<St: fmt::Display, Sym: fmt::Display> fmt::Display for Instr<St, Sym> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -188,24 +1143,128 @@ impl<St: fmt::Display, Sym: fmt::Display> fmt::Display for Instr<St, Sym> {
     }
 }
 
+pub fn read_source(path: &str) -> std::io::Result<Vec<u8>> {
+    let mut f = std::fs::File::open(path)?;
+    let sz = f.metadata()?.len() as usize;
+
+    // hackish way to put a new line at the end of source code
+    let mut buf = Vec::with_capacity(sz + 1);
+    f.read_to_end(&mut buf)?;
+    buf.push(b'\n');
+    Ok(buf)
+}
+
 pub fn parse_source<'c, 'k>(
     content: &'c [u8],
     file: &'static str,
 ) -> Result<Program<'c>, ParseErr<'c, 'k>> {
+    let path = Path::new(file);
+    let mut includes = vec![path.canonicalize().unwrap_or_else(|_| path.to_path_buf())];
+    let Module {
+        runs,
+        bb_runs,
+        mut halt_syms,
+        mut accept_syms,
+        mut reject_syms,
+        lib_halt_syms,
+        lib_accept_syms,
+        lib_reject_syms,
+        spec,
+        alphabet,
+        input,
+        sets,
+        rules,
+        ..
+    } = parse_module(content, file, &mut includes)?;
+
+    if halt_syms.is_empty() && accept_syms.is_empty() && reject_syms.is_empty() {
+        halt_syms.push("HALT");
+    }
+    // the halt states of included files stay halt states, unless the program continues from them
+    for (syms, lib) in [
+        (&mut halt_syms, lib_halt_syms),
+        (&mut accept_syms, lib_accept_syms),
+        (&mut reject_syms, lib_reject_syms),
+    ] {
+        for state in lib {
+            if !syms.contains(&state) && !rules.iter().any(|r| r.state == state) {
+                syms.push(state);
+            }
+        }
+    }
+    for &state in accept_syms.iter().chain(reject_syms.iter()) {
+        if !halt_syms.contains(&state) {
+            halt_syms.push(state);
+        }
+    }
+
+    let mut program = Program {
+        runs,
+        bb_runs,
+        halt_syms,
+        accept_syms,
+        reject_syms,
+        spec,
+        alphabet,
+        input,
+        sets,
+        rules,
+        program: Vec::new(),
+    };
+    program.expand(&[]);
+    Ok(program)
+}
+
+struct Module<'c> {
+    runs: Vec<RunCmd<'c>>,
+    bb_runs: Vec<BbRunCmd<'c>>,
+    halt_syms: Vec<&'c str>,
+    accept_syms: Vec<&'c str>,
+    reject_syms: Vec<&'c str>,
+    /// the namespaced `#halt`, `#accept` and `#reject` states of the included files
+    lib_halt_syms: Vec<&'c str>,
+    lib_accept_syms: Vec<&'c str>,
+    lib_reject_syms: Vec<&'c str>,
+    spec: Option<SpecCmd<'c>>,
+    alphabet: Option<Vec<&'c str>>,
+    input: Option<Vec<&'c str>>,
+    sets: Vec<SetCmd<'c>>,
+    macros: Vec<MacroCmd<'c>>,
+    rules: Vec<Instr<&'c str, Pat<'c>>>,
+}
+
+/// `includes` is the chain of files that are currently being parsed, used to detect cycles
+fn parse_module<'c, 'k>(
+    content: &'c [u8],
+    file: &'static str,
+    includes: &mut Vec<PathBuf>,
+) -> Result<Module<'c>, ParseErr<'c, 'k>> {
+    let content = lexer::validate_utf8(content, file).map_err(|loc| ParseErr::InvalidUtf8 { loc })?;
     let lexer = Lexer::new(content, file);
     let mut parser = Parser::new(lexer);
 
     use TokenKind::*;
 
-    let mut program: Vec<Instr<&str, &str>> = Vec::new();
+    let mut rules: Vec<Instr<&str, Pat>> = Vec::new();
     let mut runs: Vec<RunCmd> = Vec::new();
+    let mut bb_runs: Vec<BbRunCmd> = Vec::new();
+    // an `#expect` cannot follow a `#run bb` or `#run @<FILE>`
+    let mut expectable = false;
     let mut halt_syms: Vec<&str> = Vec::new();
+    let (mut accept_syms, mut reject_syms): (Vec<&str>, Vec<&str>) = (Vec::new(), Vec::new());
+    let mut lib_halt_syms: Vec<&str> = Vec::new();
+    let mut lib_accept_syms: Vec<&str> = Vec::new();
+    let mut lib_reject_syms: Vec<&str> = Vec::new();
+    let mut spec = None;
+    let mut alphabet = None;
+    let mut input = None;
+    let mut namespaces: Vec<&str> = Vec::new();
     loop {
         let token = match parser.peek_token() {
             TokenResult::Eof { .. } => break,
             TokenResult::Valid(t) => t,
             got => {
-                return Err(ParseErr {
+                return Err(ParseErr::Unexpected {
                     expected: &[Symbol, Cmd],
                     got,
                 })
@@ -217,24 +1276,514 @@ pub fn parse_source<'c, 'k>(
                 kind: Cmd,
                 text: "#run",
                 ..
-            } => runs.push(parser.parse_cmd_run()?),
+            } => match parser.parse_cmd_run()? {
+                Run::Tape(run) => {
+                    runs.push(run);
+                    expectable = true;
+                }
+                Run::File(file) => {
+                    runs.extend(file);
+                    expectable = false;
+                }
+                Run::Bb(run) => {
+                    bb_runs.push(BbRunCmd {
+                        at: runs.len(),
+                        ..run
+                    });
+                    expectable = false;
+                }
+            },
+            Token {
+                kind: Cmd,
+                text: "#expect",
+                loc,
+            } => match runs.last_mut() {
+                Some(run @ RunCmd { expect: None, .. }) if expectable => {
+                    run.expect = Some(parser.parse_cmd_expect()?)
+                }
+                _ => return Err(ParseErr::ExpectWithoutRun { loc }),
+            },
             Token {
                 kind: Cmd,
                 text: "#halt",
                 ..
             } => halt_syms = parser.parse_cmd_halt()?.states,
-            Token { kind: Symbol, .. } => program.push(parser.parse_instr()?),
+            Token {
+                kind: Cmd,
+                text: "#accept",
+                ..
+            } => accept_syms = parser.parse_cmd_halt()?.states,
+            Token {
+                kind: Cmd,
+                text: "#reject",
+                ..
+            } => reject_syms = parser.parse_cmd_halt()?.states,
+            Token {
+                kind: Cmd,
+                text: "#spec",
+                ..
+            } => spec = Some(parser.parse_cmd_spec()?),
+            Token {
+                kind: Cmd,
+                text: "#alphabet",
+                ..
+            } => alphabet = Some(parser.parse_cmd_alphabet()?),
+            Token {
+                kind: Cmd,
+                text: "#input",
+                ..
+            } => input = Some(parser.parse_cmd_alphabet()?),
+            Token {
+                kind: Cmd,
+                text: "#set",
+                ..
+            } => {
+                let set = parser.parse_cmd_set()?;
+                parser.sets.push(set);
+            }
+            Token {
+                kind: Cmd,
+                text: "#macro",
+                ..
+            } => {
+                let m = parser.parse_cmd_macro()?;
+                parser.macros.push(m);
+            }
+            Token {
+                kind: Cmd,
+                text: "#use",
+                ..
+            } => rules.extend(parser.parse_cmd_use()?),
+            Token {
+                kind: Cmd,
+                text: "#include",
+                ..
+            } => {
+                let (ns, loc, module) = parser.parse_cmd_include(includes)?;
+                if namespaces.contains(&ns) {
+                    return Err(ParseErr::NamespaceTaken { name: ns, loc });
+                }
+                namespaces.push(ns);
+
+                let prefix = |name| namespaced(ns, name);
+                for set in module.sets {
+                    parser.sets.push(SetCmd {
+                        name: prefix(set.name),
+                        syms: set.syms,
+                    });
+                }
+                for m in module.macros {
+                    let is_free = |s: &str| {
+                        !m.params.contains(&s) && !m.rules.iter().any(|r| r.state == s)
+                    };
+                    let state_name = |s| if is_free(s) { prefix(s) } else { s };
+                    let rules = m
+                        .rules
+                        .iter()
+                        .map(|r| r.rename(state_name, prefix))
+                        .collect();
+                    parser.macros.push(MacroCmd {
+                        name: prefix(m.name),
+                        params: m.params,
+                        rules,
+                        loc: m.loc,
+                    });
+                }
+                rules.extend(module.rules.iter().map(|r| r.rename(prefix, prefix)));
+
+                let halts = module.halt_syms.iter().chain(module.lib_halt_syms.iter());
+                lib_halt_syms.extend(halts.map(|&s| prefix(s)));
+                let accepts = module.accept_syms.iter().chain(module.lib_accept_syms.iter());
+                lib_accept_syms.extend(accepts.map(|&s| prefix(s)));
+                let rejects = module.reject_syms.iter().chain(module.lib_reject_syms.iter());
+                lib_reject_syms.extend(rejects.map(|&s| prefix(s)));
+            }
+            Token {
+                kind: Cmd,
+                text,
+                loc,
+            } => return Err(ParseErr::UnknownCmd { name: text, loc }),
+            Token { kind: Symbol, .. } => rules.push(parser.parse_instr()?),
             Token { kind: NewLine, .. } => parser.skip_token(),
-            tok => unreachable!("{tok:?}"),
+            got => {
+                return Err(ParseErr::Unexpected {
+                    expected: &[Symbol, Cmd],
+                    got: TokenResult::Valid(got),
+                })
+            }
         }
     }
-    if halt_syms.is_empty() {
-        halt_syms.push("HALT");
-    }
 
-    Ok(Program {
+    Ok(Module {
         runs,
+        bb_runs,
         halt_syms,
-        program,
+        accept_syms,
+        reject_syms,
+        lib_halt_syms,
+        lib_accept_syms,
+        lib_reject_syms,
+        spec,
+        alphabet,
+        input,
+        sets: parser.sets,
+        macros: parser.macros,
+        rules,
     })
 }
+
+/// Reads tapes written one per line as the cells of a `#run`, with or without the brackets, or as a string.
+/// Returns every tape with the cell its head starts on. Empty lines and comments are skipped.
+pub fn parse_tapes<'c, 'k>(
+    content: &'c [u8],
+    file: &'static str,
+) -> Result<Vec<(Vec<&'c str>, usize)>, ParseErr<'c, 'k>> {
+    use TokenKind::*;
+
+    let content = lexer::validate_utf8(content, file).map_err(|loc| ParseErr::InvalidUtf8 { loc })?;
+    let mut parser = Parser::new(Lexer::new(content, file));
+    let mut tapes = Vec::new();
+    loop {
+        match parser.peek_token() {
+            TokenResult::Eof { .. } => break,
+            TokenResult::Valid(Token { kind: NewLine, .. }) => parser.skip_token(),
+            TokenResult::Valid(Token {
+                kind: Bra | Str, ..
+            }) => {
+                let (tape, head, _) = parser.parse_initial_tape()?;
+                tapes.push((tape, head.unwrap_or(0)));
+                let _ = parser.expect_token(&[NewLine])?;
+            }
+            _ => {
+                let (tape, head) = parser.parse_marked_cells(&[Symbol, Head, NewLine])?;
+                tapes.push((tape, head.unwrap_or(0)));
+            }
+        }
+    }
+    Ok(tapes)
+}
+
+fn namespaced<'c>(ns: &str, name: &str) -> &'c str {
+    Box::leak(format!("{ns}::{name}").into_boxed_str())
+}
+
+/// every symbol that can ever be on the tape, in order of first appearance
+pub fn tape_symbols<'c>(rules: &[Instr<&'c str, Pat<'c>>], runs: &[RunCmd<'c>]) -> Vec<&'c str> {
+    let mut syms: Vec<&str> = Vec::new();
+    let pats = rules.iter().flat_map(|r| [r.read, r.write]);
+    let tapes = runs.iter().flat_map(|r| r.tape.iter().map(|&s| Pat::Sym(s)));
+    for pat in pats.chain(tapes) {
+        if let Pat::Sym(s) = pat {
+            if !syms.contains(&s) {
+                syms.push(s);
+            }
+        }
+    }
+    syms
+}
+
+/// Turns the source rules into a plain transition table.
+/// Within a state, rules with a concrete read symbol take precedence over `$SET` rules,
+/// which take precedence over `*` rules, regardless of their order.
+/// Among overlapping `$SET` or `*` rules, the first one wins.
+/// `*` rules are expanded for every symbol of `syms`.
+pub fn expand_rules<'c>(
+    rules: &[Instr<&'c str, Pat<'c>>],
+    syms: &[&'c str],
+    sets: &[SetCmd<'c>],
+) -> Vec<Instr<&'c str, &'c str>> {
+    let resolve = |write: Pat<'c>, read: &'c str| match write {
+        Pat::Sym(s) => s,
+        Pat::Same => read,
+        Pat::Set(_) | Pat::Any => unreachable!("pattern in write position"),
+    };
+
+    let mut program: Vec<Instr<&str, &str>> = Vec::new();
+    for rule in rules.iter() {
+        if let Pat::Sym(read) = rule.read {
+            program.push(Instr {
+                state: rule.state,
+                read,
+                write: resolve(rule.write, read),
+                dir: rule.dir,
+                next_state: rule.next_state,
+            });
+        }
+    }
+
+    let set_rules = rules.iter().filter_map(|r| match r.read {
+        Pat::Set(name) => {
+            let set = sets.iter().find(|s| s.name == name).expect("set is defined");
+            Some((r, &set.syms[..]))
+        }
+        _ => None,
+    });
+    let any_rules = rules
+        .iter()
+        .filter(|r| r.read == Pat::Any)
+        .map(|r| (r, syms));
+
+    for (rule, syms) in set_rules.chain(any_rules) {
+        for &read in syms.iter() {
+            if program
+                .iter()
+                .any(|i| i.state == rule.state && i.read == read)
+            {
+                continue;
+            }
+            program.push(Instr {
+                state: rule.state,
+                read,
+                write: resolve(rule.write, read),
+                dir: rule.dir,
+                next_state: rule.next_state,
+            });
+        }
+    }
+    program
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &'static str) -> Program<'static> {
+        parse_source(src.as_bytes(), "test.tur").unwrap()
+    }
+
+    /// the write symbol, direction and next state of the rule applied in `state` on `read`
+    fn applied<'c>(
+        program: &Program<'c>,
+        state: &str,
+        read: &str,
+    ) -> Option<(&'c str, Dir, &'c str)> {
+        program.lookup(state, read).map(|i| (i.write, i.dir, i.next_state))
+    }
+
+    #[test]
+    fn wildcard_matches_every_other_symbol() {
+        let program = parse(
+            "#halt H\n\
+             #run [0 1 a] I\n\
+             I * = <- H\n\
+             I 0 1 -> I\n",
+        );
+        assert_eq!(applied(&program, "I", "0"), Some(("1", Dir::Right, "I")));
+        assert_eq!(applied(&program, "I", "1"), Some(("1", Dir::Left, "H")));
+        assert_eq!(applied(&program, "I", "a"), Some(("a", Dir::Left, "H")));
+        assert_eq!(applied(&program, "I", "b"), None);
+        assert_eq!(applied(&program, "H", "0"), None);
+    }
+
+    #[test]
+    fn explicit_rules_win_over_sets_and_sets_over_wildcards() {
+        let program = parse(
+            "#halt H\n\
+             #set BIT 0 1\n\
+             #run [0 1 a] I\n\
+             I * w - H\n\
+             I $BIT b - H\n\
+             I 1 e - H\n",
+        );
+        assert_eq!(applied(&program, "I", "0"), Some(("b", Dir::Stay, "H")));
+        assert_eq!(applied(&program, "I", "1"), Some(("e", Dir::Stay, "H")));
+        assert_eq!(applied(&program, "I", "a"), Some(("w", Dir::Stay, "H")));
+    }
+
+    #[test]
+    fn first_overlapping_wildcard_wins() {
+        let program = parse(
+            "#halt H\n\
+             #set A 0 1\n\
+             #set B 1 2\n\
+             #run [0 1 2] I\n\
+             I $A a - H\n\
+             I $B b - H\n\
+             I * x - H\n\
+             I * y - H\n",
+        );
+        assert_eq!(applied(&program, "I", "1"), Some(("a", Dir::Stay, "H")));
+        assert_eq!(applied(&program, "I", "2"), Some(("b", Dir::Stay, "H")));
+        assert!(program.program.iter().all(|i| i.write != "y"));
+    }
+
+    #[test]
+    fn wildcard_expands_for_declared_and_extra_symbols() {
+        let mut program = parse(
+            "#halt H\n\
+             #run [0] I\n\
+             I * = -> I\n",
+        );
+        assert_eq!(applied(&program, "I", "z"), None);
+        program.expand(&["z"]);
+        assert_eq!(applied(&program, "I", "z"), Some(("z", Dir::Right, "I")));
+
+        let program = parse(
+            "#halt H\n\
+             #alphabet 0 1 _\n\
+             #run [0] I\n\
+             I * = -> I\n",
+        );
+        assert_eq!(applied(&program, "I", "_"), Some(("_", Dir::Right, "I")));
+    }
+
+    #[test]
+    fn macro_substitutes_params() {
+        let program = parse(
+            "#halt H\n\
+             #run [a b '#'] GO\n\
+             #macro SEEK entry target next\n\
+             entry target = - next\n\
+             entry * = -> entry\n\
+             #end\n\
+             #use SEEK GO '#' H\n",
+        );
+        assert_eq!(applied(&program, "GO", "#"), Some(("#", Dir::Stay, "H")));
+        assert_eq!(applied(&program, "GO", "a"), Some(("a", Dir::Right, "GO")));
+        assert!(program.program.iter().all(|i| i.state == "GO"));
+    }
+
+    #[test]
+    fn macro_locals_are_fresh_per_use() {
+        let program = parse(
+            "#halt H\n\
+             #run [0 1] A\n\
+             #macro SKIP entry next\n\
+             entry * = -> MID\n\
+             MID * MID <- next\n\
+             #end\n\
+             #use SKIP A B\n\
+             #use SKIP B H\n",
+        );
+        assert_eq!(applied(&program, "A", "0"), Some(("0", Dir::Right, "SKIP#1::MID")));
+        assert_eq!(applied(&program, "B", "0"), Some(("0", Dir::Right, "SKIP#2::MID")));
+        // the symbol named like the local state is written as it is
+        assert_eq!(applied(&program, "SKIP#1::MID", "1"), Some(("MID", Dir::Left, "B")));
+        assert_eq!(applied(&program, "SKIP#2::MID", "1"), Some(("MID", Dir::Left, "H")));
+    }
+
+    /// writes `files` into a fresh directory named after `test` and parses the first one
+    fn parse_files(test: &str, files: &[(&str, &str)]) -> Result<Program<'static>, String> {
+        let dir = std::env::temp_dir().join(format!("turir-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        let file = dir.join(files[0].0).to_string_lossy().into_owned();
+        let file: &'static str = Box::leak(file.into_boxed_str());
+        let content = Box::leak(read_source(file).unwrap().into_boxed_slice());
+        parse_source(content, file).map_err(|err| err.to_string())
+    }
+
+    fn parse_files_err(test: &str, files: &[(&str, &str)]) -> String {
+        match parse_files(test, files) {
+            Ok(_) => panic!("{test} is parsed without an error"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn include_namespaces_nested_files() {
+        let program = parse_files(
+            "include-nested",
+            &[
+                (
+                    "main.tur",
+                    "#include \"lib/bits.tur\"\n\
+                     #run [0 1] bits::GO\n\
+                     START $bits::BIT = -> bits::GO\n",
+                ),
+                (
+                    "lib/bits.tur",
+                    "#include \"flip.tur\"\n\
+                     #set BIT 0 1\n\
+                     #run [0] GO\n\
+                     GO 0 0 -> flip::FLIP\n",
+                ),
+                ("lib/flip.tur", "#halt DONE\nFLIP 1 0 - DONE\n"),
+            ],
+        )
+        .unwrap();
+        // the path of an include is relative to the file that includes it
+        assert_eq!(applied(&program, "START", "1"), Some(("1", Dir::Right, "bits::GO")));
+        assert_eq!(applied(&program, "bits::GO", "0"), Some(("0", Dir::Right, "bits::flip::FLIP")));
+        assert_eq!(
+            applied(&program, "bits::flip::FLIP", "1"),
+            Some(("0", Dir::Stay, "bits::flip::DONE"))
+        );
+        // only the runs of the main file are kept
+        assert_eq!(program.runs.len(), 1);
+        assert!(program.halt_syms.contains(&"bits::flip::DONE"));
+    }
+
+    #[test]
+    fn included_halt_states_can_be_continued() {
+        let program = parse_files(
+            "include-continue",
+            &[
+                (
+                    "main.tur",
+                    "#include \"inc.tur\"\n\
+                     #halt H\n\
+                     #run [1 0] inc::INC\n\
+                     inc::DONE * = - H\n",
+                ),
+                (
+                    "inc.tur",
+                    "#halt DONE\n\
+                     #reject OVERFLOW\n\
+                     INC 1 0 -> INC\n\
+                     INC 0 1 - DONE\n\
+                     INC * = - OVERFLOW\n",
+                ),
+            ],
+        )
+        .unwrap();
+        assert!(!program.halt_syms.contains(&"inc::DONE"));
+        assert!(program.is_reject("inc::OVERFLOW"));
+        assert_eq!(applied(&program, "inc::DONE", "1"), Some(("1", Dir::Stay, "H")));
+    }
+
+    #[test]
+    fn include_cycle() {
+        let err = parse_files_err(
+            "include-cycle",
+            &[
+                ("main.tur", "#include \"a.tur\"\n"),
+                ("a.tur", "#include \"b.tur\"\n"),
+                ("b.tur", "#include \"a.tur\"\n"),
+            ],
+        );
+        assert!(err.ends_with("b.tur:1:1: Including \"a.tur\" creates a cycle"), "{err}");
+    }
+
+    #[test]
+    fn include_same_namespace_twice() {
+        let err = parse_files_err(
+            "include-namespace",
+            &[
+                ("main.tur", "#include \"a/util.tur\"\n#include \"b/util.tur\"\n"),
+                ("a/util.tur", "A 0 0 -> A\n"),
+                ("b/util.tur", "B 0 0 -> B\n"),
+            ],
+        );
+        assert!(err.ends_with("main.tur:2:1: Namespace `util` is already included"), "{err}");
+    }
+
+    #[test]
+    fn errors_name_the_included_file() {
+        let err = parse_files_err(
+            "include-error",
+            &[
+                ("main.tur", "#include \"lib/bad.tur\"\n"),
+                ("lib/bad.tur", "// a rule without a direction\nA 0 0 A\n"),
+            ],
+        );
+        assert!(err.contains("lib/bad.tur:2:7:"), "{err}");
+
+        let err = parse_files_err("include-missing", &[("main.tur", "\n#include \"nope.tur\"\n")]);
+        assert!(err.contains("main.tur:2:1: Could not include \"nope.tur\""), "{err}");
+    }
+}
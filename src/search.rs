@@ -0,0 +1,351 @@
+use crate::bb::{Table, STATES, SYMBOLS};
+use crate::parser::Dir;
+use std::collections::VecDeque;
+
+/// the halt state of found machines, written as `Z`
+const HALT: u8 = STATES.len() as u8 - 1;
+/// edge records kept by the translated cycle decider
+const RECORDS: usize = 32;
+/// cells next to an edge record kept by the translated cycle decider
+const WINDOW: usize = 256;
+
+/// a halting machine found by [`search`]
+#[derive(Debug, Clone)]
+pub struct Found {
+    pub table: Table,
+    pub steps: usize,
+    pub ones: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// every machine that was run, halting or not
+    pub machines: usize,
+    pub halting: usize,
+    /// repeat the same configuration
+    pub cyclers: usize,
+    /// repeat the same configuration shifted along the tape while moving into blank cells
+    pub translated: usize,
+    /// reached the step limit without being decided
+    pub undecided: usize,
+    /// the halting machines with the most steps, descending
+    pub by_steps: Vec<Found>,
+    /// the halting machines with the most non-blank cells, descending
+    pub by_ones: Vec<Found>,
+}
+
+enum Verdict {
+    /// reached the undefined transition of `state` on `read`
+    Undefined {
+        state: usize,
+        read: u8,
+        steps: usize,
+        ones: usize,
+    },
+    Cycler,
+    Translated,
+    Undecided,
+}
+
+/// the configuration of a machine when the head first visits a cell beyond one end of the tape
+struct Record {
+    state: usize,
+    head: usize,
+    /// the furthest the head went back from the edge since this record
+    far: usize,
+    /// cells up to `WINDOW` away from the edge towards the rest of the tape, starting from the head
+    cells: Vec<u8>,
+}
+
+/// Runs the machine on a blank tape until it reaches an undefined transition,
+/// a decider proves that it never halts, or `max_steps` is reached.
+/// `tape` is reused between machines so it is only allocated as far as the machines go.
+fn simulate(table: &Table, max_steps: usize, tape: &mut Vec<u8>) -> Verdict {
+    // the head is kept at least `WINDOW` cells away from both ends, the tape grows before it gets closer
+    tape.clear();
+    tape.resize(2 * WINDOW + 1, 0);
+    let mut head = WINDOW;
+    let (mut lo, mut hi) = (head, head);
+    let (mut state, mut steps) = (0, 0);
+
+    // configuration snapshots at steps that are powers of 2 for the cycle decider
+    let mut snapshot: Option<(usize, usize, usize, usize, Vec<u8>)> = None;
+    let (mut left, mut right): (VecDeque<Record>, VecDeque<Record>) = Default::default();
+
+    while steps < max_steps {
+        let read = tape[head];
+        let Some((write, dir, next)) = table.transitions[state * table.symbols + read as usize] else {
+            let ones = tape[lo..=hi].iter().filter(|&&c| c != 0).count();
+            // the halting transition writes a 1
+            return Verdict::Undefined {
+                state,
+                read,
+                steps: steps + 1,
+                ones: ones + (read == 0) as usize,
+            };
+        };
+        tape[head] = write;
+        state = next as usize;
+        steps += 1;
+        match dir {
+            Dir::Left => head -= 1,
+            Dir::Right => head += 1,
+            Dir::Stay => {}
+        }
+        if head < WINDOW {
+            // grow by the current length so moves stay amortized constant time,
+            // every position moves along with the cells
+            let len = tape.len();
+            tape.splice(0..0, std::iter::repeat_n(0, len));
+            (head, lo, hi) = (head + len, lo + len, hi + len);
+            if let Some((_, h, l, r, _)) = snapshot.as_mut() {
+                (*h, *l, *r) = (*h + len, *l + len, *r + len);
+            }
+            for rec in left.iter_mut().chain(right.iter_mut()) {
+                (rec.head, rec.far) = (rec.head + len, rec.far + len);
+            }
+        }
+        if head + WINDOW >= tape.len() {
+            tape.resize(tape.len() * 2, 0);
+        }
+
+        if let Some((s, h, l, r, cells)) = &snapshot {
+            if (*s, *h, *l, *r) == (state, head, lo, hi) && cells[..] == tape[lo..=hi] {
+                return Verdict::Cycler;
+            }
+        }
+        if steps.is_power_of_two() {
+            snapshot = Some((state, head, lo, hi, tape[lo..=hi].to_vec()));
+        }
+
+        for rec in right.iter_mut() {
+            rec.far = rec.far.min(head);
+        }
+        for rec in left.iter_mut() {
+            rec.far = rec.far.max(head);
+        }
+        if head > hi {
+            hi = head;
+            // the cells the machine read since `rec` must repeat shifted by the distance it moved
+            let repeats = right.iter().any(|rec| {
+                let d = head - rec.head;
+                let start = rec.head + 1 - rec.cells.len();
+                rec.state == state
+                    && rec.far >= start
+                    && (rec.far..=rec.head).all(|p| rec.cells[p - start] == tape[p + d])
+            });
+            if repeats {
+                return Verdict::Translated;
+            }
+            let start = head.saturating_sub(WINDOW - 1);
+            right.push_back(Record {
+                state,
+                head,
+                far: head,
+                cells: tape[start..=head].to_vec(),
+            });
+            if right.len() > RECORDS {
+                right.pop_front();
+            }
+        }
+        if head < lo {
+            lo = head;
+            let repeats = left.iter().any(|rec| {
+                let d = rec.head - head;
+                rec.state == state
+                    && rec.far < rec.head + rec.cells.len()
+                    && (rec.head..=rec.far).all(|p| rec.cells[p - rec.head] == tape[p - d])
+            });
+            if repeats {
+                return Verdict::Translated;
+            }
+            let end = (head + WINDOW - 1).min(tape.len() - 1);
+            left.push_back(Record {
+                state,
+                head,
+                far: head,
+                cells: tape[head..=end].to_vec(),
+            });
+            if left.len() > RECORDS {
+                left.pop_front();
+            }
+        }
+    }
+    Verdict::Undecided
+}
+
+/// keeps the `top` best machines by `key` in descending order
+fn rank(list: &mut Vec<Found>, found: &Found, top: usize, key: fn(&Found) -> usize) {
+    let at = list.partition_point(|f| key(f) >= key(found));
+    if at < top {
+        list.insert(at, found.clone());
+        list.truncate(top);
+    }
+}
+
+struct Search {
+    states: usize,
+    symbols: usize,
+    max_steps: usize,
+    top: usize,
+    summary: Summary,
+    /// the tape every machine is simulated on
+    tape: Vec<u8>,
+}
+
+impl Search {
+    fn expand(&mut self, table: &mut Table) {
+        self.summary.machines += 1;
+        let (state, read) = match simulate(table, self.max_steps, &mut self.tape) {
+            Verdict::Undefined {
+                state,
+                read,
+                steps,
+                ones,
+            } => {
+                let i = state * self.symbols + read as usize;
+                table.transitions[i] = Some((1, Dir::Right, HALT));
+                let found = Found {
+                    table: table.clone(),
+                    steps,
+                    ones,
+                };
+                table.transitions[i] = None;
+                self.summary.halting += 1;
+                rank(&mut self.summary.by_steps, &found, self.top, |f| f.steps);
+                rank(&mut self.summary.by_ones, &found, self.top, |f| f.ones);
+                (state, read as usize)
+            }
+            Verdict::Cycler => return self.summary.cyclers += 1,
+            Verdict::Translated => return self.summary.translated += 1,
+            Verdict::Undecided => return self.summary.undecided += 1,
+        };
+
+        // a machine without undefined transitions left cannot halt
+        let defined = table.transitions.iter().filter(|t| t.is_some()).count();
+        if defined + 2 > table.transitions.len() {
+            return;
+        }
+
+        // tree normal form: states and symbols are used in order, so only one of the
+        // machines that differ by renaming them is enumerated
+        let mut used_states = state;
+        let mut used_symbols = read;
+        for (i, t) in table.transitions.iter().enumerate() {
+            if let Some((write, _, next)) = *t {
+                used_states = used_states.max(i / self.symbols).max(next as usize);
+                used_symbols = used_symbols.max(write as usize);
+            }
+        }
+        let states = (used_states + 2).min(self.states);
+        let symbols = (used_symbols + 2).min(self.symbols);
+        // machines that are mirror images of each other behave the same, the first move is always right
+        let dirs: &[Dir] = match defined {
+            0 => &[Dir::Right],
+            _ => &[Dir::Left, Dir::Right],
+        };
+
+        let i = state * self.symbols + read;
+        for write in 0..symbols {
+            for &dir in dirs {
+                for next in 0..states {
+                    table.transitions[i] = Some((write as u8, dir, next as u8));
+                    self.expand(table);
+                }
+            }
+        }
+        table.transitions[i] = None;
+    }
+}
+
+/// Enumerates the machines with `states` states and `symbols` symbols in tree normal form:
+/// starting from a machine without transitions, every time a machine reaches an undefined
+/// transition it is counted as halting there, then continued with every possible definition of it.
+/// Machines are run for at most `max_steps` steps, the `top` machines by steps and by ones are kept.
+pub fn search(states: usize, symbols: usize, max_steps: usize, top: usize) -> Summary {
+    assert!(states > 0 && states < STATES.len(), "unsupported state count");
+    assert!(symbols > 1 && symbols <= SYMBOLS.len(), "unsupported symbol count");
+
+    let mut search = Search {
+        states,
+        symbols,
+        max_steps,
+        top,
+        summary: Summary::default(),
+        tape: Vec::new(),
+    };
+    let mut table = Table {
+        states,
+        symbols,
+        transitions: vec![None; states * symbols],
+    };
+    search.expand(&mut table);
+    search.summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulate_notation(notation: &str, max_steps: usize) -> Verdict {
+        simulate(&Table::parse(notation).unwrap(), max_steps, &mut Vec::new())
+    }
+
+    #[test]
+    fn decides_cyclers() {
+        // goes back and forth between the first two cells
+        assert!(matches!(simulate_notation("0RB---_0LA---", 1000), Verdict::Cycler));
+    }
+
+    #[test]
+    fn decides_translated_cyclers() {
+        // walks right over blank cells forever
+        assert!(matches!(simulate_notation("1RA---", 1000), Verdict::Translated));
+        // goes back and forth over the cells it wrote before it moves on
+        assert!(matches!(simulate_notation("1LB1RB_1RA0RA", 1000), Verdict::Translated));
+    }
+
+    #[test]
+    fn stops_on_undefined_transitions() {
+        let verdict = simulate_notation("1RB---_1LA---", 1000);
+        let Verdict::Undefined { state, read, steps, ones } = verdict else {
+            panic!("1RB---_1LA--- reaches A on 1");
+        };
+        // the undefined transition counts as the halting step
+        assert_eq!((state, read, steps, ones), (0, 1, 3, 2));
+    }
+
+    #[test]
+    fn stops_at_the_step_limit() {
+        // a counter that is neither a cycler nor a translated cycler
+        assert!(matches!(simulate_notation("1RB1LB_1LA1RZ", 3), Verdict::Undecided));
+    }
+
+    #[test]
+    fn finds_busy_beaver_2() {
+        let summary = search(2, 2, 1000, 1);
+        let Summary { machines, halting, cyclers, translated, undecided, .. } = summary;
+        assert_eq!(machines, halting + cyclers + translated + undecided);
+        assert_eq!(undecided, 0);
+        assert_eq!(summary.by_steps[0].steps, 6);
+        assert_eq!(summary.by_ones[0].ones, 4);
+        assert_eq!(summary.by_ones[0].table.to_string(), "1RB1LB_1LA1RZ");
+    }
+
+    #[test]
+    fn finds_busy_beaver_3() {
+        let summary = search(3, 2, 1000, 3);
+        assert_eq!(summary.by_steps[0].steps, 21);
+        assert_eq!(summary.by_ones[0].ones, 6);
+        // descending
+        assert!(summary.by_steps.windows(2).all(|w| w[0].steps >= w[1].steps));
+    }
+
+    #[test]
+    fn enumerates_tree_normal_form() {
+        // the machines that differ only by renaming states or symbols, or by mirroring, are counted once:
+        // with one state there is the empty machine and `0RA` and `1RA` for A on 0
+        assert_eq!(search(1, 2, 100, 1).machines, 3);
+        assert_eq!(search(2, 2, 1000, 1).machines, 61);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::parser::{Dir, Instr, Program, RunCmd};
+use std::error::Error;
+use std::fmt;
+
+pub const BLANK: &str = "_";
+
+#[derive(Debug)]
+pub struct TmsErr {
+    /// starts from 0
+    pub line: usize,
+    pub msg: &'static str,
+}
+
+impl Error for TmsErr {}
+
+impl fmt::Display for TmsErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.line + 1, self.msg)
+    }
+}
+
+fn fields(line: &str) -> Vec<&str> {
+    line.split(',').map(str::trim).collect()
+}
+
+fn symbol(sym: &str) -> bool {
+    sym.chars().count() == 1
+}
+
+/// Reads a single tape machine in the text format of turingmachinesimulator.com.
+/// Every rule is a `state,read` line followed by a `next_state,write,direction` line,
+/// directions are `<`, `>` and `-`, symbols are single characters and `_` is the blank.
+/// The `init` state gets a run on an empty tape, `accept` states become `#accept` states.
+pub fn parse(content: &str) -> Result<Program<'_>, TmsErr> {
+    let mut init = None;
+    let mut halt_syms = Vec::new();
+    let mut program = Vec::new();
+    let mut pending: Option<(usize, &str, &str)> = None;
+
+    for (i, line) in content.lines().enumerate() {
+        let line = match line.find("//") {
+            Some(c) => &line[..c],
+            None => line,
+        }
+        .trim();
+        let err = |msg| Err(TmsErr { line: i, msg });
+        if line.is_empty() {
+            continue;
+        }
+
+        if pending.is_none() {
+            if let Some((key, value)) = line.split_once(':') {
+                match key.trim() {
+                    "name" => {}
+                    "init" => init = Some(value.trim()),
+                    "accept" => halt_syms.extend(fields(value).into_iter().filter(|s| !s.is_empty())),
+                    _ => return err("unknown key, expected name, init or accept"),
+                }
+                continue;
+            }
+        }
+
+        match (pending.take(), &fields(line)[..]) {
+            (None, &[state, read]) => {
+                if !symbol(read) {
+                    return err("symbols must be a single character");
+                }
+                pending = Some((i, state, read));
+            }
+            (None, _) => return err("expected `state,read`, only single tape machines are supported"),
+            (Some((_, state, read)), &[next_state, write, dir]) => {
+                if !symbol(write) {
+                    return err("symbols must be a single character");
+                }
+                let dir = match dir {
+                    "<" => Dir::Left,
+                    ">" => Dir::Right,
+                    "-" => Dir::Stay,
+                    _ => return err("direction must be one of `<`, `>` or `-`"),
+                };
+                program.push(Instr {
+                    state,
+                    read,
+                    write,
+                    dir,
+                    next_state,
+                });
+            }
+            (Some(_), _) => return err("expected `next_state,write,direction`"),
+        }
+    }
+    if let Some((line, ..)) = pending {
+        return Err(TmsErr {
+            line,
+            msg: "rule is missing its `next_state,write,direction` line",
+        });
+    }
+
+    let runs = init
+        .map(|state| RunCmd {
+            tape: vec![BLANK],
+            head: 0,
+            state,
+            expect: None,
+        })
+        .into_iter()
+        .collect();
+    let accept_syms = halt_syms.clone();
+    let mut program = Program::from_table(runs, halt_syms, None, program);
+    program.accept_syms = accept_syms;
+    Ok(program)
+}
+
+#[derive(Debug)]
+pub enum ExportErr<'c> {
+    /// a symbol that is not a single character
+    Symbol(&'c str),
+    /// the blank cannot be renamed to `_` since `_` is used already
+    Blank(&'c str),
+}
+
+impl<'c> Error for ExportErr<'c> {}
+
+impl<'c> fmt::Display for ExportErr<'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportErr::Symbol(sym) => write!(f, "symbol '{sym}' is not a single character"),
+            ExportErr::Blank(blank) => write!(
+                f,
+                "blank '{blank}' cannot be written as `{BLANK}`, `{BLANK}` is another symbol"
+            ),
+        }
+    }
+}
+
+/// Writes the transition table, the blank of the first run is renamed to `_`.
+/// States and symbols cannot contain `,`, which is not checked.
+pub fn export<'c>(program: &Program<'c>, name: &str, init: &str) -> Result<String, ExportErr<'c>> {
+    let blank = program
+        .runs
+        .first()
+        .and_then(|r| r.tape.last())
+        .or(program.spec.as_ref().map(|s| &s.blank))
+        .copied()
+        .unwrap_or(BLANK);
+    let syms = program.program.iter().flat_map(|i| [i.read, i.write]);
+    let renamed = |sym: &'c str| -> Result<&'c str, ExportErr<'c>> {
+        if sym == blank {
+            return Ok(BLANK);
+        }
+        if sym == BLANK {
+            return Err(ExportErr::Blank(blank));
+        }
+        match symbol(sym) {
+            true => Ok(sym),
+            false => Err(ExportErr::Symbol(sym)),
+        }
+    };
+    for sym in syms {
+        renamed(sym)?;
+    }
+
+    let mut out = format!("name: {name}\ninit: {init}\n");
+    // halting in any other state rejects
+    let accept: Vec<&str> = program
+        .halt_syms
+        .iter()
+        .filter(|s| !program.is_reject(s))
+        .copied()
+        .collect();
+    if !accept.is_empty() {
+        out += &format!("accept: {}\n", accept.join(", "));
+    }
+    for instr in program.program.iter() {
+        let dir = match instr.dir {
+            Dir::Left => "<",
+            Dir::Right => ">",
+            Dir::Stay => "-",
+        };
+        out += &format!(
+            "\n{},{}\n{},{},{dir}\n",
+            instr.state,
+            renamed(instr.read)?,
+            instr.next_state,
+            renamed(instr.write)?
+        );
+    }
+    Ok(out)
+}
@@ -0,0 +1,182 @@
+use crate::interp::{Machine, Stop};
+use crate::json::{Str, Strs};
+use crate::parser::{Instr, Program, RunCmd};
+use std::fmt;
+
+/// A machine-readable trace record, displayed as a single line JSON object.
+/// Cell positions are relative to the first cell of the initial tape and can be negative.
+pub enum Record<'a, 'c> {
+    /// the initial configuration of a run
+    Run {
+        run: usize,
+        state: &'c str,
+        head: isize,
+        tape: &'a [&'c str],
+    },
+    /// an applied instruction, `head` is the position it was applied at
+    /// and `diff` holds every cell that was written or added to the tape by it
+    Step {
+        run: usize,
+        step: usize,
+        state: &'c str,
+        read: &'c str,
+        instr: &'a Instr<&'c str, &'c str>,
+        head: isize,
+        diff: Vec<(isize, &'c str)>,
+    },
+    /// the final configuration of a run, `first_cell` is the position of the first cell of `tape`
+    Halt {
+        run: usize,
+        stop: Stop<'c>,
+        state: &'c str,
+        steps: usize,
+        head: isize,
+        first_cell: isize,
+        tape: &'a [&'c str],
+    },
+}
+
+impl<'a, 'c> fmt::Display for Record<'a, 'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Record::Run {
+                run,
+                state,
+                head,
+                tape,
+            } => write!(
+                f,
+                r#"{{"type":"run","run":{run},"state":{},"head":{head},"tape":{}}}"#,
+                Str(state),
+                Strs(tape)
+            ),
+            Record::Step {
+                run,
+                step,
+                state,
+                read,
+                instr,
+                head,
+                diff,
+            } => {
+                write!(
+                    f,
+                    r#"{{"type":"step","run":{run},"step":{step},"state":{},"read":{},"head":{head},"#,
+                    Str(state),
+                    Str(read)
+                )?;
+                write!(
+                    f,
+                    r#""instr":{{"state":{},"read":{},"write":{},"dir":"{}","next_state":{}}},"diff":["#,
+                    Str(instr.state),
+                    Str(instr.read),
+                    Str(instr.write),
+                    instr.dir.name(),
+                    Str(instr.next_state)
+                )?;
+                for (i, (cell, sym)) in diff.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, r#"{{"cell":{cell},"symbol":{}}}"#, Str(sym))?;
+                }
+                write!(f, "]}}")
+            }
+            Record::Halt {
+                run,
+                stop,
+                state,
+                steps,
+                head,
+                first_cell,
+                tape,
+            } => {
+                let reason = match stop {
+                    Stop::Halted => "halted",
+                    Stop::Missing { .. } => "missing_transition",
+                    Stop::StepLimit => "step_limit",
+                };
+                write!(
+                    f,
+                    r#"{{"type":"halt","run":{run},"reason":"{reason}","state":{},"steps":{steps},"head":{head},"first_cell":{first_cell},"tape":{}"#,
+                    Str(state),
+                    Strs(tape)
+                )?;
+                if let Stop::Missing { read, .. } = stop {
+                    write!(f, r#","read":{}"#, Str(read))?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Runs the machine and hands every record of the run to `emit`, stops early if `emit` fails.
+pub fn trace_run<'c, E>(
+    program: &Program<'c>,
+    run: &RunCmd<'c>,
+    index: usize,
+    max_steps: Option<usize>,
+    mut emit: impl FnMut(&Record<'_, 'c>) -> Result<(), E>,
+) -> Result<(&'c str, Stop<'c>), E> {
+    let mut machine = Machine::new(program, run);
+    emit(&Record::Run {
+        run: index,
+        state: machine.state,
+        head: machine.head as isize,
+        tape: &machine.tape,
+    })?;
+
+    let pos = |m: &Machine, i: usize| i as isize - m.offset as isize;
+    let stop = loop {
+        if max_steps.is_some_and(|max| machine.steps >= max) && !machine.is_halted() {
+            break Stop::StepLimit;
+        }
+        let instr = match machine.next_instr() {
+            Ok(instr) => instr,
+            Err(stop) => break stop,
+        };
+
+        let (state, read, head) = (machine.state, machine.read(), pos(&machine, machine.head));
+        let (len, offset) = (machine.tape.len(), machine.offset);
+        machine.apply(instr);
+
+        let mut diff = Vec::new();
+        // cells added on the left
+        for i in 0..machine.offset - offset {
+            diff.push((pos(&machine, i), machine.tape[i]));
+        }
+        if instr.write != read || head >= (len - offset) as isize {
+            diff.push((head, instr.write));
+        }
+        // cells added on the right, other than the written one
+        let old_end = (len - offset) as isize;
+        for i in 0..machine.tape.len() {
+            let p = pos(&machine, i);
+            if p >= old_end && p != head {
+                diff.push((p, machine.tape[i]));
+            }
+        }
+
+        emit(&Record::Step {
+            run: index,
+            step: machine.steps,
+            state,
+            read,
+            instr,
+            head,
+            diff,
+        })?;
+    };
+
+    emit(&Record::Halt {
+        run: index,
+        stop,
+        state: machine.state,
+        steps: machine.steps,
+        head: pos(&machine, machine.head),
+        first_cell: pos(&machine, 0),
+        tape: &machine.tape,
+    })?;
+    Ok((machine.state, stop))
+}
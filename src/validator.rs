@@ -0,0 +1,289 @@
+use crate::parser::{Dir, Instr, Pat, Program, RunCmd};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Diagnostic<'p, 'c> {
+    /// two rules share the same state and read symbol, only the first one is ever used
+    Duplicate {
+        first: &'p Instr<&'c str, &'c str>,
+        second: &'p Instr<&'c str, &'c str>,
+    },
+    /// a stay-put rule that rewrites the same symbol into the same state never makes progress
+    StayLoop { instr: &'p Instr<&'c str, &'c str> },
+    /// a `*` rule of a state that already has an earlier `*` rule
+    ShadowedWildcard {
+        rule: &'p Instr<&'c str, Pat<'c>>,
+        by: &'p Instr<&'c str, Pat<'c>>,
+    },
+    /// a `$SET` or `*` rule whose every symbol is already handled by other rules
+    UnusedPattern { rule: &'p Instr<&'c str, Pat<'c>> },
+    /// a `#run` starts from a state that has no rules and is not a halt state
+    UndefinedStart { state: &'c str },
+    /// a rule reads or writes a symbol that is not in the `#alphabet`
+    UndeclaredSymbol {
+        rule: &'p Instr<&'c str, Pat<'c>>,
+        sym: &'c str,
+    },
+    /// a run tape has a symbol that is not in the `#alphabet`,
+    /// or not in the `#input` alphabet for every cell but the blank at the end
+    UndeclaredTapeSymbol {
+        run: &'p RunCmd<'c>,
+        sym: &'c str,
+        input: bool,
+    },
+    /// a symbol of `#input` that is not in the `#alphabet`
+    UndeclaredInput { sym: &'c str },
+    /// a state that is not a halt state has no rule for a symbol of the `#alphabet`,
+    /// or for a symbol that can be on the tape with `--completeness`
+    MissingTransition { state: &'c str, read: &'c str },
+    /// a state that is both an `#accept` and a `#reject` state
+    AcceptAndReject { state: &'c str },
+}
+
+impl<'p, 'c> Diagnostic<'p, 'c> {
+    pub fn is_error(&self) -> bool {
+        match self {
+            Diagnostic::Duplicate { .. } => true,
+            Diagnostic::StayLoop { .. } => false,
+            Diagnostic::ShadowedWildcard { .. } => false,
+            Diagnostic::UnusedPattern { .. } => false,
+            Diagnostic::UndefinedStart { .. } => true,
+            Diagnostic::UndeclaredSymbol { .. } => true,
+            Diagnostic::UndeclaredTapeSymbol { .. } => true,
+            Diagnostic::UndeclaredInput { .. } => true,
+            Diagnostic::MissingTransition { .. } => false,
+            Diagnostic::AcceptAndReject { .. } => true,
+        }
+    }
+}
+
+impl<'p, 'c> fmt::Display for Diagnostic<'p, 'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = if self.is_error() { "error" } else { "warning" };
+        write!(f, "{level}: ")?;
+        match self {
+            Diagnostic::Duplicate { first, second } => write!(
+                f,
+                "`{second}` is never used, state '{}' and read '{}' is already defined by `{first}`",
+                second.state, second.read
+            ),
+            Diagnostic::StayLoop { instr } => {
+                write!(f, "`{instr}` stays on the same cell forever")
+            }
+            Diagnostic::ShadowedWildcard { rule, by } => {
+                write!(f, "`{rule}` is never used, it is shadowed by `{by}`")
+            }
+            Diagnostic::UnusedPattern { rule } => write!(
+                f,
+                "`{rule}` is never used, every symbol it matches in state '{}' is handled by other rules",
+                rule.state
+            ),
+            Diagnostic::UndefinedStart { state } => {
+                write!(f, "run starts from state '{state}' which has no rules")
+            }
+            Diagnostic::UndeclaredSymbol { rule, sym } => {
+                write!(f, "`{rule}` uses '{sym}' which is not in the alphabet")
+            }
+            Diagnostic::UndeclaredTapeSymbol { run, sym, input } => write!(
+                f,
+                "`{run}` starts on '{sym}' which is not in the {} alphabet",
+                if *input { "input" } else { "tape" }
+            ),
+            Diagnostic::UndeclaredInput { sym } => {
+                write!(f, "input symbol '{sym}' is not in the alphabet")
+            }
+            Diagnostic::MissingTransition { state, read } => {
+                write!(f, "state '{state}' has no rule for '{read}'")
+            }
+            Diagnostic::AcceptAndReject { state } => {
+                write!(f, "state '{state}' cannot both accept and reject")
+            }
+        }
+    }
+}
+
+pub fn validate<'p, 'c>(program: &'p Program<'c>) -> Vec<Diagnostic<'p, 'c>> {
+    let mut diags = Vec::new();
+
+    for instr in program.program.iter() {
+        if let Some(first) = program
+            .lookup(instr.state, instr.read)
+            .filter(|first| !std::ptr::eq(*first, instr))
+        {
+            diags.push(Diagnostic::Duplicate {
+                first,
+                second: instr,
+            });
+        }
+
+        if instr.dir == Dir::Stay && instr.state == instr.next_state && instr.read == instr.write {
+            diags.push(Diagnostic::StayLoop { instr });
+        }
+    }
+
+    let syms = program.symbols();
+    let set_syms = |name| &program.sets.iter().find(|s| s.name == name).unwrap().syms;
+    for (i, rule) in program.rules.iter().enumerate() {
+        let matches = match rule.read {
+            Pat::Sym(_) | Pat::Same => continue,
+            Pat::Set(name) => set_syms(name),
+            Pat::Any => &syms,
+        };
+        if let Some(by) = program.rules[..i]
+            .iter()
+            .find(|r| r.state == rule.state && r.read == Pat::Any && rule.read == Pat::Any)
+        {
+            diags.push(Diagnostic::ShadowedWildcard { rule, by });
+            continue;
+        }
+        // explicit rules and earlier sets always win, `*` also loses to later sets
+        let handled = |sym: &&str| {
+            program.rules.iter().enumerate().any(|(j, r)| {
+                r.state == rule.state
+                    && match r.read {
+                        Pat::Sym(s) => s == *sym,
+                        Pat::Set(name) => {
+                            (j < i || rule.read == Pat::Any) && set_syms(name).contains(sym)
+                        }
+                        Pat::Any | Pat::Same => false,
+                    }
+            })
+        };
+        if matches.iter().all(handled) {
+            diags.push(Diagnostic::UnusedPattern { rule });
+        }
+    }
+
+    for run in program.runs.iter() {
+        if !program.halt_syms.contains(&run.state)
+            && !program.program.iter().any(|i| i.state == run.state)
+        {
+            diags.push(Diagnostic::UndefinedStart { state: run.state });
+        }
+    }
+
+    for &state in program.accept_syms.iter() {
+        if program.is_reject(state) {
+            diags.push(Diagnostic::AcceptAndReject { state });
+        }
+    }
+
+    diags.extend(validate_symbols(program));
+    if let Some(alphabet) = &program.alphabet {
+        diags.extend(missing_transitions(program, alphabet));
+    }
+
+    diags
+}
+
+/// Reports the rules and run tapes with symbols outside of the `#alphabet` and `#input`,
+/// `turir run` and `turir test` check them before executing anything.
+pub fn validate_symbols<'p, 'c>(program: &'p Program<'c>) -> Vec<Diagnostic<'p, 'c>> {
+    let mut diags = Vec::new();
+
+    if let Some(input) = &program.input {
+        // symbols outside of the `#alphabet` are reported by `validate_alphabet`
+        let declared = |s: &&str| program.alphabet.as_ref().is_none_or(|a| a.contains(s));
+        for run in program.runs.iter() {
+            let (_, cells) = run.tape.split_last().expect("tape cannot be empty");
+            if let Some(&sym) = cells.iter().find(|s| !input.contains(s) && declared(s)) {
+                diags.push(Diagnostic::UndeclaredTapeSymbol {
+                    run,
+                    sym,
+                    input: true,
+                });
+            }
+        }
+    }
+    if let Some(alphabet) = &program.alphabet {
+        validate_alphabet(program, alphabet, &mut diags);
+    }
+
+    diags
+}
+
+fn validate_alphabet<'p, 'c>(
+    program: &'p Program<'c>,
+    alphabet: &[&'c str],
+    diags: &mut Vec<Diagnostic<'p, 'c>>,
+) {
+    for sym in program.input.iter().flatten() {
+        if !alphabet.contains(sym) {
+            diags.push(Diagnostic::UndeclaredInput { sym });
+        }
+    }
+
+    for rule in program.rules.iter() {
+        let mut syms = Vec::new();
+        for pat in [rule.read, rule.write] {
+            match pat {
+                Pat::Sym(s) => syms.push(s),
+                Pat::Set(name) => {
+                    let set = program.sets.iter().find(|s| s.name == name).unwrap();
+                    syms.extend(set.syms.iter());
+                }
+                Pat::Any | Pat::Same => {}
+            }
+        }
+        if let Some(sym) = syms.into_iter().find(|s| !alphabet.contains(s)) {
+            diags.push(Diagnostic::UndeclaredSymbol { rule, sym });
+        }
+    }
+
+    for run in program.runs.iter() {
+        if let Some(&sym) = run.tape.iter().find(|s| !alphabet.contains(s)) {
+            diags.push(Diagnostic::UndeclaredTapeSymbol {
+                run,
+                sym,
+                input: false,
+            });
+        }
+    }
+
+}
+
+/// Reports the missing transitions for every symbol that can be on the tape without an `#alphabet`:
+/// the cells of the initial tapes and of the `#spec` inputs, their blanks and every written symbol.
+pub fn completeness<'p, 'c>(program: &'p Program<'c>) -> Vec<Diagnostic<'p, 'c>> {
+    let mut syms: Vec<&str> = Vec::new();
+    let tapes = program.runs.iter().flat_map(|r| r.tape.iter());
+    let spec = program
+        .spec
+        .iter()
+        .flat_map(|s| s.alphabet.iter().chain([&s.blank]));
+    let writes = program.program.iter().map(|i| &i.write);
+    for &sym in tapes.chain(spec).chain(writes) {
+        if !syms.contains(&sym) {
+            syms.push(sym);
+        }
+    }
+    missing_transitions(program, &syms)
+}
+
+/// every state that is not a halt state and has no rule for one of `syms`,
+/// states are in order of appearance
+fn missing_transitions<'p, 'c>(program: &'p Program<'c>, syms: &[&'c str]) -> Vec<Diagnostic<'p, 'c>> {
+    let mut states: Vec<&str> = Vec::new();
+    let starts = program.runs.iter().map(|r| r.state).chain(program.spec.iter().map(|s| s.state));
+    let reached = starts.chain(
+        program
+            .program
+            .iter()
+            .flat_map(|i| [i.state, i.next_state]),
+    );
+    for state in reached {
+        if !states.contains(&state) && !program.halt_syms.contains(&state) {
+            states.push(state);
+        }
+    }
+
+    let mut diags = Vec::new();
+    for state in states {
+        for &read in syms.iter() {
+            if program.lookup(state, read).is_none() {
+                diags.push(Diagnostic::MissingTransition { state, read });
+            }
+        }
+    }
+    diags
+}
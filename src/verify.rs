@@ -0,0 +1,245 @@
+use crate::interp::{Machine, Stop};
+use crate::parser::{Program, RunCmd};
+use std::error::Error;
+use std::fmt;
+
+/// the inputs up to a length are too many to count in a `usize`
+#[derive(Debug)]
+pub struct TooManyInputs {
+    pub symbols: usize,
+    pub max_len: usize,
+}
+
+impl Error for TooManyInputs {}
+
+impl fmt::Display for TooManyInputs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let TooManyInputs { symbols, max_len } = self;
+        write!(f, "input space too large, {symbols} symbols up to length {max_len}")
+    }
+}
+
+/// every word over `alphabet` with at most `max_len` symbols, shortest first
+pub fn inputs<'a, 'c>(
+    alphabet: &'a [&'c str],
+    max_len: usize,
+) -> Result<impl Iterator<Item = Vec<&'c str>> + 'a, TooManyInputs> {
+    let count = |len: usize| alphabet.len().checked_pow(u32::try_from(len).ok()?);
+    let total = (0..=max_len).try_fold(0usize, |total, len| total.checked_add(count(len)?));
+    if total.is_none() {
+        return Err(TooManyInputs {
+            symbols: alphabet.len(),
+            max_len,
+        });
+    }
+    Ok((0..=max_len).flat_map(move |len| {
+        (0..alphabet.len().pow(len as u32)).map(move |mut n| {
+            let mut word = vec![""; len];
+            for cell in word.iter_mut().rev() {
+                *cell = alphabet[n % alphabet.len()];
+                n /= alphabet.len();
+            }
+            word
+        })
+    }))
+}
+
+/// the tape without the blank cells on both ends
+pub fn trim_blanks<'a, 'c>(tape: &'a [&'c str], blank: &str) -> &'a [&'c str] {
+    let start = tape.iter().position(|&c| c != blank);
+    let end = tape.iter().rposition(|&c| c != blank);
+    match (start, end) {
+        (Some(start), Some(end)) => &tape[start..=end],
+        _ => &[],
+    }
+}
+
+/// what the state a machine halted in says about its input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Accept,
+    Reject,
+    Halt,
+}
+
+/// the configuration a machine ended in
+#[derive(Debug, Clone)]
+pub struct Outcome<'c> {
+    pub tape: Vec<&'c str>,
+    pub head: usize,
+    pub state: &'c str,
+    pub verdict: Verdict,
+    pub steps: usize,
+    pub stop: Stop<'c>,
+}
+
+impl<'c> Outcome<'c> {
+    pub fn trimmed(&self, blank: &str) -> &[&'c str] {
+        trim_blanks(&self.tape, blank)
+    }
+
+    /// Two outcomes are the same if both machines halted with the same verdict and tape,
+    /// both ran into a missing transition with the same tape, or both hit the step limit.
+    /// The names of the states are not compared.
+    pub fn same_as(&self, other: &Outcome, blank: &str) -> bool {
+        match (self.stop, other.stop) {
+            (Stop::Halted, Stop::Halted) => {
+                self.verdict == other.verdict && self.trimmed(blank) == other.trimmed(blank)
+            }
+            (Stop::Missing { .. }, Stop::Missing { .. }) => self.trimmed(blank) == other.trimmed(blank),
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl<'c> fmt::Display for Outcome<'c> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[ ")?;
+        for t in self.tape.iter() {
+            write!(f, "{t} ")?;
+        }
+        write!(
+            f,
+            "] state {}, head {}, steps {} ({})",
+            self.state, self.head, self.steps, self.stop
+        )
+    }
+}
+
+/// runs `program` from `state` on `input` followed by a `blank` cell
+pub fn run_input<'c>(
+    program: &Program<'c>,
+    state: &'c str,
+    input: &[&'c str],
+    blank: &'c str,
+    max_steps: usize,
+) -> Outcome<'c> {
+    let mut tape = input.to_vec();
+    tape.push(blank);
+    let run = RunCmd {
+        tape,
+        head: 0,
+        state,
+        expect: None,
+    };
+    let mut machine = Machine::new(program, &run);
+    let stop = machine.run(Some(max_steps));
+    let verdict = match machine.state {
+        s if program.is_accept(s) => Verdict::Accept,
+        s if program.is_reject(s) => Verdict::Reject,
+        _ => Verdict::Halt,
+    };
+    Outcome {
+        tape: machine.tape,
+        head: machine.head,
+        state: machine.state,
+        verdict,
+        steps: machine.steps,
+        stop,
+    }
+}
+
+#[derive(Debug)]
+pub struct Expected<'c> {
+    pub input: Vec<&'c str>,
+    pub output: Vec<&'c str>,
+}
+
+/// Parses an expected-output file, one `<INPUT> => <OUTPUT>` pair per line
+/// with the cells separated by whitespace. Returns the number of the first malformed line on error.
+pub fn parse_expected(content: &str) -> Result<Vec<Expected<'_>>, usize> {
+    let mut pairs = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((input, output)) = line.split_once("=>") else {
+            return Err(i + 1);
+        };
+        pairs.push(Expected {
+            input: input.split_whitespace().collect(),
+            output: output.split_whitespace().collect(),
+        });
+    }
+    Ok(pairs)
+}
+
+/// a machine and the state it starts from
+pub struct Entry<'p, 'c> {
+    pub program: &'p Program<'c>,
+    pub state: &'c str,
+}
+
+/// an input with the outcomes of two machines on it
+pub type Difference<'c> = (Vec<&'c str>, Outcome<'c>, Outcome<'c>);
+
+/// runs both machines on every input, shortest first, and returns the first one they disagree on
+pub fn first_difference<'c>(
+    a: Entry<'_, 'c>,
+    b: Entry<'_, 'c>,
+    alphabet: &[&'c str],
+    max_len: usize,
+    blank: &'c str,
+    max_steps: usize,
+) -> Result<Option<Difference<'c>>, TooManyInputs> {
+    Ok(inputs(alphabet, max_len)?.find_map(|input| {
+        let out_a = run_input(a.program, a.state, &input, blank, max_steps);
+        let out_b = run_input(b.program, b.state, &input, blank, max_steps);
+        (!out_a.same_as(&out_b, blank)).then_some((input, out_a, out_b))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_source;
+
+    fn parse(src: &'static str) -> Program<'static> {
+        parse_source(src.as_bytes(), "test.tur").unwrap()
+    }
+
+    /// the first input over `0 1` on which the machines starting from `I` differ
+    fn difference(a: &'static str, b: &'static str) -> Option<Vec<&'static str>> {
+        let (mut a, mut b) = (parse(a), parse(b));
+        // like `turir equiv`, `*` rules match the whole alphabet
+        a.expand(&["0", "1", "_"]);
+        b.expand(&["0", "1", "_"]);
+        let (a, b) = (Entry { program: &a, state: "I" }, Entry { program: &b, state: "I" });
+        let diff = first_difference(a, b, &["0", "1"], 3, "_", 100).unwrap();
+        diff.map(|(input, _, _)| input)
+    }
+
+    #[test]
+    fn counts_inputs() {
+        assert_eq!(inputs(&["0", "1"], 3).unwrap().count(), 1 + 2 + 4 + 8);
+        let first: Vec<_> = inputs(&["0", "1"], 2).unwrap().take(4).collect();
+        assert_eq!(first, [vec![], vec!["0"], vec!["1"], vec!["0", "0"]]);
+        assert_eq!(inputs(&[], 5).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn too_many_inputs() {
+        assert!(inputs(&["0", "1"], 64).is_err());
+        assert!(inputs(&["0", "1"], usize::MAX).is_err());
+        assert!(inputs(&["0", "1", "2"], 1000).is_err());
+    }
+
+    #[test]
+    fn accept_differs_from_reject() {
+        let accept = "#accept Y\n#reject N\nI * = - Y\n";
+        let reject = "#accept Y\n#reject N\nI * = - N\n";
+        assert_eq!(difference(accept, reject), Some(vec![]));
+        // the names of the states do not matter
+        assert_eq!(difference(accept, "#accept OK\nI * = - OK\n"), None);
+        assert_eq!(difference("#halt H\nI * = - H\n", "#halt STOP\nI * = - STOP\n"), None);
+    }
+
+    #[test]
+    fn missing_transitions_compare_tapes() {
+        // both stop on the first blank, one of them after flipping the bits
+        let keep = "#halt H\nI 0 0 -> I\nI 1 1 -> I\n";
+        let flip = "#halt H\nI 0 1 -> I\nI 1 0 -> I\n";
+        assert_eq!(difference(keep, keep), None);
+        assert_eq!(difference(keep, flip), Some(vec!["0"]));
+    }
+}